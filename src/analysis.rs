@@ -0,0 +1,575 @@
+//! Computations on the library, independent from the Web API and from the user interface.
+use crate::api::models::{FullArtist, Track};
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::Serialize;
+use std::cmp::{min, Ordering};
+use std::collections::HashMap;
+use std::{fmt, mem};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Track repeating another one, with its position in the list it was found in
+#[derive(Debug, Clone)]
+pub struct Duplicate<'a> {
+    pub index: usize,
+    pub track: &'a Track,
+    /// What the track has in common with the kept one, e.g. the ISRC or the normalized artist and title
+    pub key: String,
+    pub reason: Reason,
+}
+
+/// Tracks that are the same song
+#[derive(Debug, Clone)]
+pub struct Group<'a> {
+    /// Position of the track kept according to the criteria
+    pub kept: usize,
+    /// Key of the kept track
+    pub key: String,
+    pub duplicates: Vec<Duplicate<'a>>,
+}
+
+/// Release decade with the tracks from it
+#[derive(Debug)]
+pub struct Decade<'a> {
+    /// e.g. "1980s"
+    pub decade: String,
+    pub tracks: Vec<&'a Track>,
+}
+
+/// Genre with the artists belonging to it and their tracks.
+/// Every artist is also a genre of its own, so that playlists can be made of single artists.
+#[derive(Debug)]
+pub struct Genre<'a> {
+    pub genre: String,
+    /// Names of the artists
+    pub artists: Vec<String>,
+    pub tracks: Vec<&'a Track>,
+}
+
+/// Number of tracks by an artist or from an album
+#[derive(Debug)]
+pub struct Count<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub count: u32,
+}
+
+#[derive(Debug)]
+pub struct LibraryStats<'a> {
+    pub songs: usize,
+    /// Sorted from the most to the least tracks
+    pub albums: Vec<Count<'a>>,
+    /// Sorted from the most to the least tracks, every artist of a track is counted
+    pub artists: Vec<Count<'a>>,
+}
+
+/// Default minimum similarity of two normalized titles for the fuzzy strategy
+pub const DEFAULT_THRESHOLD: f64 = 0.9;
+
+/// Words starting a feature credit, e.g. "(feat. X)" or "ft. X"
+const FEATURE_WORDS: [&str; 4] = ["feat", "ft", "featuring", "with"];
+/// Words of suffixes naming another release of the same recording, e.g. "Remastered 2009" or "Radio Edit"
+const RELEASE_WORDS: [&str; 15] = [
+    "remaster",
+    "remastered",
+    "edit",
+    "edition",
+    "deluxe",
+    "anniversary",
+    "expanded",
+    "bonus",
+    "single",
+    "album",
+    "version",
+    "mono",
+    "stereo",
+    "explicit",
+    "clean",
+];
+/// Words of suffixes naming a different recording, those suffixes are kept, e.g. "Acoustic Version"
+const RECORDING_WORDS: [&str; 10] = [
+    "live",
+    "acoustic",
+    "remix",
+    "mix",
+    "demo",
+    "instrumental",
+    "unplugged",
+    "karaoke",
+    "reprise",
+    "orchestral",
+];
+
+/// Roman numerals of parts and movements, other words made of these letters are too rare to matter
+const ROMAN_NUMERALS: [&str; 20] = [
+    "i", "ii", "iii", "iv", "v", "vi", "vii", "viii", "ix", "x", "xi", "xii", "xiii", "xiv", "xv",
+    "xvi", "xvii", "xviii", "xix", "xx",
+];
+
+/// How tracks are recognized as the same song
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Same name by the same artists
+    #[default]
+    Name,
+    /// Same recording, as told by the ISRC. Tracks without one are never duplicates.
+    Isrc,
+    /// Same main artist and similar titles once normalized, see `normalize_title`
+    Fuzzy,
+}
+
+/// Which track of a group of duplicates is kept, the others are the duplicates.
+/// When the tracks are equal in that regard, the one from the album with the lower ID is kept.
+#[derive(ValueEnum, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Keep {
+    /// The one from an album rather than a single or compilation
+    #[default]
+    Album,
+    /// The one from a single
+    Single,
+    /// The one from a compilation
+    Compilation,
+    /// The one released first
+    Earliest,
+    /// The one released last
+    Latest,
+    /// The most popular one
+    Popular,
+    /// The explicit one
+    Explicit,
+    /// The clean one
+    Clean,
+    /// The one added first, tracks without a date are added last
+    FirstAdded,
+    /// The one at the first position
+    First,
+    /// The one at the last position
+    Last,
+}
+
+/// How duplicates are found and which one of each group is kept
+#[derive(Debug, Clone, Copy)]
+pub struct Criteria {
+    pub strategy: Strategy,
+    /// Minimum similarity of the normalized titles, from 0 to 1, with the fuzzy strategy
+    pub threshold: f64,
+    pub keep: Keep,
+}
+
+impl Default for Criteria {
+    fn default() -> Self {
+        Criteria {
+            strategy: Strategy::default(),
+            threshold: DEFAULT_THRESHOLD,
+            keep: Keep::default(),
+        }
+    }
+}
+
+/// Why a track was found to be a duplicate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    SameName,
+    SameIsrc,
+    SameNormalizedTitle,
+    /// Similarity of the normalized titles, from 0 to 1
+    SimilarTitle(f64),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::SameName => write!(f, "same name"),
+            Reason::SameIsrc => write!(f, "same ISRC"),
+            Reason::SameNormalizedTitle => write!(f, "same normalized title"),
+            Reason::SimilarTitle(similarity) => {
+                write!(f, "similar title ({:.0}%)", similarity * 100.0)
+            }
+        }
+    }
+}
+
+/// What duplicates have in common, depending on the strategy.
+/// Only tracks in the same group are compared, by their title.
+struct Key {
+    group: String,
+    title: String,
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.title.is_empty() {
+            write!(f, "{}", self.group)
+        } else {
+            write!(f, "{} - {}", self.group, self.title)
+        }
+    }
+}
+
+impl Strategy {
+    fn key(&self, track: &Track) -> Option<Key> {
+        match self {
+            Strategy::Name => Some(Key {
+                group: track.artists.iter().map(|artist| &artist.name).join(", "),
+                title: track.name.to_owned(),
+            }),
+            // Sometimes written with dashes, e.g. US-AAA-91-00001
+            Strategy::Isrc => track.external_ids.isrc.as_ref().map(|isrc| Key {
+                group: isrc.replace('-', "").to_uppercase(),
+                title: String::new(),
+            }),
+            // The other artists are often credited in the title instead
+            Strategy::Fuzzy => Some(Key {
+                group: words(&track.artists[0].name).join(" "),
+                title: normalize_title(&track.name),
+            }),
+        }
+    }
+
+    /// Why two titles of the same group match, if they do
+    fn matches(&self, title: &str, other: &str, threshold: f64) -> Option<Reason> {
+        match self {
+            Strategy::Name if title == other => Some(Reason::SameName),
+            Strategy::Isrc => Some(Reason::SameIsrc),
+            Strategy::Fuzzy if title == other => Some(Reason::SameNormalizedTitle),
+            // e.g. "Part 1" and "Part 2", or "No. 1" and "No. 2", are different pieces however similar
+            Strategy::Fuzzy if numbers(title).ne(numbers(other)) => None,
+            Strategy::Fuzzy => {
+                let similarity = strsim::normalized_levenshtein(title, other);
+                Some(Reason::SimilarTitle(similarity)).filter(|_| similarity >= threshold)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Words of a normalized title that are numbers, in digits or roman numerals
+fn numbers(title: &str) -> impl Iterator<Item = &str> {
+    title
+        .split(' ')
+        .filter(|word| word.chars().any(|c| c.is_ascii_digit()) || ROMAN_NUMERALS.contains(word))
+}
+
+/// Lowercase words of a text without diacritics, anything but letters and digits separates words
+fn words(text: &str) -> Vec<String> {
+    text.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Whether the words of a suffix are a feature credit or name another release of the same recording
+fn is_removable_suffix(words: &[String]) -> bool {
+    let contains = |list: &[&str]| words.iter().any(|word| list.contains(&&word[..]));
+    words
+        .first()
+        .is_some_and(|word| FEATURE_WORDS.contains(&&word[..]))
+        || (contains(&RELEASE_WORDS) && !contains(&RECORDING_WORDS))
+}
+
+/// Title of a track reduced to what tells songs apart.
+/// Feature credits and suffixes like " - Remastered 2009" or "(Radio Edit)" are removed, but not the ones
+/// naming another recording like "(Live)". What's left is lowercased, without diacritics and punctuation.
+pub fn normalize_title(title: &str) -> String {
+    // Parts between brackets are suffixes, as are the parts after a dash
+    let mut main = String::new();
+    let mut suffixes = vec![];
+    let mut depth = 0;
+    for c in title.chars() {
+        match c {
+            '(' | '[' => {
+                if depth == 0 {
+                    suffixes.push(String::new());
+                }
+                depth += 1;
+            }
+            ')' | ']' if depth > 0 => depth -= 1,
+            _ if depth > 0 => suffixes.last_mut().unwrap().push(c),
+            _ => main.push(c),
+        }
+    }
+    let mut parts = main.split(" - ").flat_map(|part| part.split(" – "));
+    let main = parts.next().unwrap_or_default();
+    suffixes.extend(parts.map(String::from));
+
+    let mut normalized = words(main);
+    // A feature credit without brackets goes until the end of the main part
+    if let Some(credit) = normalized
+        .iter()
+        .skip(1)
+        .position(|word| FEATURE_WORDS[..3].contains(&&word[..]))
+    {
+        normalized.truncate(credit + 1);
+    }
+    for suffix in suffixes {
+        let suffix = words(&suffix);
+        if !is_removable_suffix(&suffix) {
+            normalized.extend(suffix);
+        }
+    }
+    if normalized.is_empty() {
+        // e.g. "(Intro)", better than matching every such title
+        words(title).join(" ")
+    } else {
+        normalized.join(" ")
+    }
+}
+
+impl Keep {
+    /// `Greater` when the track should be kept over the other one
+    fn compare(&self, track: &Kept, other: &Kept) -> Ordering {
+        let album_type = |album_type| {
+            (track.track.album.album_type == album_type)
+                .cmp(&(other.track.album.album_type == album_type))
+        };
+        // Only compared up to the least precise of the two, "1999" isn't earlier than "1999-03-01"
+        let (date, other_date) = (
+            &track.track.album.release_date,
+            &other.track.album.release_date,
+        );
+        let precision = min(date.len(), other_date.len());
+        let release_date = date.get(..precision).cmp(&other_date.get(..precision));
+        match self {
+            Keep::Album => album_type("album"),
+            Keep::Single => album_type("single"),
+            Keep::Compilation => album_type("compilation"),
+            Keep::Earliest => release_date.reverse(),
+            Keep::Latest => release_date,
+            Keep::Popular => track.track.popularity.cmp(&other.track.popularity),
+            Keep::Explicit => track.track.explicit.cmp(&other.track.explicit),
+            Keep::Clean => other.track.explicit.cmp(&track.track.explicit),
+            Keep::FirstAdded => match (track.added_at, other.added_at) {
+                (Some(added_at), Some(other_added_at)) => other_added_at.cmp(added_at),
+                (added_at, other_added_at) => added_at.is_some().cmp(&other_added_at.is_some()),
+            },
+            Keep::First => other.index.cmp(&track.index),
+            Keep::Last => track.index.cmp(&other.index),
+        }
+    }
+
+    /// Whether the track should be kept over the previous one with the same key
+    fn prefers(&self, track: &Kept, previous: &Kept) -> bool {
+        self.compare(track, previous)
+            .then_with(|| previous.track.album.id.cmp(&track.track.album.id))
+            == Ordering::Greater
+    }
+}
+
+/// Track kept so far, others are compared to it
+struct Kept<'a> {
+    index: usize,
+    track: &'a Track,
+    added_at: Option<&'a str>,
+    key: Key,
+}
+
+/// Find tracks that are the same song according to the strategy.
+/// `added_at` has when each track was added to the list, it can be empty if that's unknown.
+/// Groups are returned in the order of their first track, with only their duplicates in the order they were found.
+pub fn group_duplicates<'a>(
+    tracks: &'a [Track],
+    added_at: &[Option<&'a str>],
+    criteria: &Criteria,
+) -> Vec<Group<'a>> {
+    let strategy = criteria.strategy;
+    let mut groups: Vec<(Kept, Vec<Duplicate>)> = Vec::new();
+    // Indices in `groups` by the part of the key that has to be equal
+    let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let key = match strategy.key(track) {
+            Some(key) => key,
+            None => continue,
+        };
+        let candidates = by_key.entry(key.group.to_owned()).or_default();
+        // The group whose kept track is the most similar, the first one on ties
+        let matched = candidates
+            .iter()
+            .filter_map(|&group| {
+                let kept = &groups[group].0;
+                let reason = strategy.matches(&key.title, &kept.key.title, criteria.threshold)?;
+                let similarity = match reason {
+                    Reason::SimilarTitle(similarity) => similarity,
+                    _ => 1.0,
+                };
+                Some((similarity, reason, group))
+            })
+            .fold(None, |best: Option<(f64, _, _)>, current| match best {
+                Some(best) if best.0 >= current.0 => Some(best),
+                _ => Some(current),
+            });
+        let current = Kept {
+            index,
+            track,
+            added_at: added_at.get(index).copied().flatten(),
+            key,
+        };
+        match matched {
+            Some((_, reason, group)) => {
+                let (kept, duplicates) = &mut groups[group];
+                let duplicate = if criteria.keep.prefers(&current, kept) {
+                    mem::replace(kept, current)
+                } else {
+                    current
+                };
+                duplicates.push(Duplicate {
+                    index: duplicate.index,
+                    track: duplicate.track,
+                    key: duplicate.key.to_string(),
+                    reason,
+                });
+            }
+            None => {
+                candidates.push(groups.len());
+                groups.push((current, Vec::new()));
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, duplicates)| !duplicates.is_empty())
+        .map(|(kept, duplicates)| Group {
+            kept: kept.index,
+            key: kept.key.to_string(),
+            duplicates,
+        })
+        .collect()
+}
+
+/// Duplicates of all the groups found by `group_duplicates`, sorted by their position
+pub fn find_duplicates<'a>(
+    tracks: &'a [Track],
+    added_at: &[Option<&'a str>],
+    criteria: &Criteria,
+) -> Vec<Duplicate<'a>> {
+    group_duplicates(tracks, added_at, criteria)
+        .into_iter()
+        .flat_map(|group| group.duplicates)
+        .sorted_by_key(|duplicate| duplicate.index)
+        .collect()
+}
+
+/// Group tracks by the decade their album was released in, from the oldest decade
+pub fn group_by_decade<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Vec<Decade<'a>> {
+    let mut decade_map = HashMap::new();
+    for track in tracks {
+        let decade = format!("{}0s", &track.album.release_date[0..3]);
+        decade_map
+            .entry(decade)
+            .and_modify(|v: &mut Vec<&Track>| v.push(track))
+            .or_insert_with(|| vec![track]);
+    }
+
+    decade_map
+        .into_iter()
+        .sorted_by(|(k1, _), (k2, _)| k1.cmp(k2))
+        .map(|(decade, tracks)| Decade { decade, tracks })
+        .collect()
+}
+
+/// IDs of the main artist of each track, without repetitions.
+/// These are the artists whose information is needed to group the tracks by genre.
+pub fn main_artists<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Vec<&'a str> {
+    tracks
+        .into_iter()
+        .map(|track| &track.artists[0].id[..])
+        .unique()
+        .collect()
+}
+
+/// Group tracks by the genres of their main artist, from the genre with the most tracks
+pub fn group_by_genre<'a>(
+    tracks: impl IntoIterator<Item = &'a Track>,
+    artists: &[FullArtist],
+) -> Vec<Genre<'a>> {
+    let mut artist_map = HashMap::new();
+    for track in tracks {
+        artist_map
+            .entry(&track.artists[0].id)
+            .and_modify(|artist_tracks: &mut Vec<&Track>| {
+                artist_tracks.push(track);
+            })
+            .or_insert_with(|| vec![track]);
+    }
+
+    let mut genre_map: HashMap<&str, Vec<&FullArtist>> = HashMap::new();
+    for artist in artists {
+        for genre in &artist.genres {
+            genre_map
+                .entry(genre)
+                .and_modify(|artists| artists.push(artist))
+                .or_insert_with(|| vec![artist]);
+        }
+
+        genre_map.insert(&artist.name, vec![artist]);
+    }
+
+    genre_map
+        .into_iter()
+        .map(|(genre, artists)| Genre {
+            genre: String::from(genre),
+            tracks: artists
+                .iter()
+                .flat_map(|artist| artist_map.get(&artist.id).into_iter().flatten())
+                .copied()
+                .collect(),
+            artists: artists
+                .into_iter()
+                .map(|artist| artist.name.to_owned())
+                .collect(),
+        })
+        .sorted_by(|g1, g2| {
+            g2.tracks
+                .len()
+                .cmp(&g1.tracks.len())
+                .then_with(|| g1.genre.cmp(&g2.genre))
+        })
+        .collect()
+}
+
+/// Count the songs, albums and artists of the library
+pub fn library_stats<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> LibraryStats<'a> {
+    let mut songs = 0;
+    let mut artist_counter = HashMap::new();
+    let mut album_counter = HashMap::new();
+
+    for track in tracks {
+        songs += 1;
+        for artist in &track.artists {
+            artist_counter
+                .entry(&artist.id)
+                .and_modify(|c: &mut Count| c.count += 1)
+                .or_insert(Count {
+                    id: &artist.id,
+                    name: &artist.name,
+                    count: 1,
+                });
+        }
+
+        album_counter
+            .entry(&track.album.id)
+            .and_modify(|c: &mut Count| c.count += 1)
+            .or_insert(Count {
+                id: &track.album.id,
+                name: &track.album.name,
+                count: 1,
+            });
+    }
+
+    let by_count = |counter: HashMap<_, Count<'a>>| {
+        counter
+            .into_values()
+            .sorted_by(|v1, v2| v2.count.cmp(&v1.count).then_with(|| v1.name.cmp(v2.name)))
+            .collect()
+    };
+    LibraryStats {
+        songs,
+        albums: by_count(album_counter),
+        artists: by_count(artist_counter),
+    }
+}
@@ -0,0 +1,267 @@
+/// Paths relative to the API base URL, absolute URLs returned by the API can be used in their place
+pub mod endpoints {
+    pub const GET_USER: &str = "/me";
+    /// Tracks unavailable in the user's market are then relinked to available ones, see `Track::saved_id`
+    pub const SAVED_TRACKS: &str = "/me/tracks?limit=50&market=from_token";
+    pub const SAVED_TRACKS_REMOVAL: &str = "/me/tracks";
+    pub const ALL_PLAYLISTS: &str = "/me/playlists?limit=50";
+    pub const PLAYLIST_CREATION: &str = "/users/{user_id}/playlists";
+    pub const PLAYLIST_TRACKS: &str = "/playlists/{playlist_id}/tracks";
+    pub const ARTISTS_INFO: &str = "/artists";
+}
+
+pub mod models {
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value;
+
+    #[derive(Deserialize, Debug)]
+    pub struct Paging<T> {
+        pub items: Vec<T>,
+        pub limit: u64,
+        pub next: Option<String>,
+        pub total: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SavedTrack {
+        /// ISO 8601 timestamp of when the song was liked
+        pub added_at: String,
+        pub track: Track,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Track {
+        pub name: String,
+        pub artists: Vec<SimplifiedArtist>,
+        pub album: SimplifiedAlbum,
+        pub id: String,
+        pub uri: String,
+        // Only used by analyses, which can do without them rather than failing to decode the whole page
+        #[serde(default)]
+        pub duration_ms: u64,
+        /// From 0 to 100, based on how much the track has been played recently
+        #[serde(default)]
+        pub popularity: u32,
+        #[serde(default)]
+        pub explicit: bool,
+        #[serde(default)]
+        pub external_ids: ExternalIds,
+        #[serde(default)]
+        pub disc_number: u32,
+        #[serde(default)]
+        pub track_number: u32,
+        /// Only known when the tracks were requested for a market
+        pub is_playable: Option<bool>,
+        /// Track originally requested, when Spotify replaced it with one available in the market
+        pub linked_from: Option<LinkedTrack>,
+    }
+
+    impl Track {
+        /// ID the track is saved under in the library, which has to be used to remove it.
+        /// It differs from `id` when Spotify relinked it to a track playable in the user's market.
+        pub fn saved_id(&self) -> &str {
+            self.linked_from
+                .as_ref()
+                .map_or(&self.id, |linked| &linked.id)
+        }
+
+        /// URI the track is saved under in playlists, see `saved_id`
+        pub fn saved_uri(&self) -> &str {
+            self.linked_from
+                .as_ref()
+                .map_or(&self.uri, |linked| &linked.uri)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+    pub struct ExternalIds {
+        /// International Standard Recording Code, shared by the releases of the same recording
+        pub isrc: Option<String>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct LinkedTrack {
+        pub id: String,
+        pub uri: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SimplifiedArtist {
+        pub name: String,
+        pub id: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct SimplifiedAlbum {
+        pub album_type: String,
+        pub name: String,
+        pub release_date: String,
+        /// Either "year", "month" or "day", depending on how much of the release date is known
+        pub release_date_precision: String,
+        pub total_tracks: u32,
+        /// Cover art in various sizes, the widest first
+        pub images: Vec<Image>,
+        pub id: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Image {
+        pub url: String,
+        pub width: Option<u32>,
+        pub height: Option<u32>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct SimplifiedPlaylist {
+        pub name: String,
+        pub owner: User,
+        pub tracks: Tracks,
+        pub snapshot_id: String,
+        pub id: String,
+        pub uri: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct PlaylistTrack {
+        /// None when the item is no longer available on Spotify
+        pub track: Option<PlaylistItem>,
+        /// ISO 8601 timestamp, unknown for items added long ago
+        pub added_at: Option<String>,
+        /// Only the ID of the user is known, unknown for items added long ago
+        pub added_by: Option<User>,
+    }
+
+    impl PlaylistTrack {
+        /// The item if it's a Spotify track, not an episode or a local file
+        pub fn track(&self) -> Option<&Track> {
+            match &self.track {
+                Some(PlaylistItem::Track(track)) => Some(track),
+                _ => None,
+            }
+        }
+
+        /// URI of a track or an episode, which can be added to or removed from playlists.
+        /// Local files can't be, even though they have an URI.
+        pub fn uri(&self) -> Option<&str> {
+            match &self.track {
+                Some(PlaylistItem::Track(track)) => Some(track.saved_uri()),
+                Some(PlaylistItem::Episode(episode)) => Some(&episode.uri),
+                Some(PlaylistItem::Local(_)) | None => None,
+            }
+        }
+    }
+
+    /// Told apart by their `type` and `is_local` fields, local files are tracks as well.
+    /// A track that doesn't decode is an error, rather than being mistaken for another kind of item.
+    #[derive(Debug, Clone)]
+    // Nearly all items are tracks, boxing them would only add an allocation to each
+    #[allow(clippy::large_enum_variant)]
+    pub enum PlaylistItem {
+        Track(Track),
+        Episode(Episode),
+        Local(LocalTrack),
+    }
+
+    /// Fields telling the kind of a playlist item apart, along with the rest of the item
+    #[derive(Serialize, Deserialize)]
+    struct TaggedItem {
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(default)]
+        is_local: bool,
+        #[serde(flatten)]
+        item: Value,
+    }
+
+    impl<'de> Deserialize<'de> for PlaylistItem {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let tagged = TaggedItem::deserialize(deserializer)?;
+            let item = match (&tagged.kind[..], tagged.is_local) {
+                (_, true) => serde_json::from_value(tagged.item).map(PlaylistItem::Local),
+                ("track", false) => serde_json::from_value(tagged.item).map(PlaylistItem::Track),
+                ("episode", false) => {
+                    serde_json::from_value(tagged.item).map(PlaylistItem::Episode)
+                }
+                (kind, false) => {
+                    return Err(de::Error::custom(format!(
+                        "unknown playlist item type \"{}\"",
+                        kind
+                    )))
+                }
+            };
+            item.map_err(de::Error::custom)
+        }
+    }
+
+    impl Serialize for PlaylistItem {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let (kind, is_local, item) = match self {
+                PlaylistItem::Track(track) => ("track", false, serde_json::to_value(track)),
+                PlaylistItem::Episode(episode) => ("episode", false, serde_json::to_value(episode)),
+                PlaylistItem::Local(local) => ("track", true, serde_json::to_value(local)),
+            };
+            TaggedItem {
+                kind: String::from(kind),
+                is_local,
+                item: item.map_err(ser::Error::custom)?,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Episode {
+        pub name: String,
+        pub id: String,
+        pub uri: String,
+        pub show: Show,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Show {
+        pub name: String,
+    }
+
+    /// File from the user's device, added to the playlist by a desktop client
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct LocalTrack {
+        pub name: String,
+        /// e.g. spotify:local:Artist:Album:Name:180
+        pub uri: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct Tracks {
+        pub href: String,
+        pub total: u64,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    pub struct User {
+        pub id: String,
+        pub display_name: Option<String>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct ArtistsResponse {
+        pub artists: Vec<FullArtist>,
+    }
+
+    /// Body of unsuccessful responses
+    #[derive(Deserialize, Debug)]
+    pub struct ErrorResponse {
+        pub error: ErrorObject,
+    }
+
+    #[derive(Deserialize, Debug)]
+    pub struct ErrorObject {
+        pub message: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct FullArtist {
+        pub id: String,
+        pub name: String,
+        pub genres: Vec<String>,
+    }
+}
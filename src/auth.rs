@@ -1,9 +1,17 @@
-use std::error::Error;
-use std::io::{BufRead, BufReader, Write};
+use crate::config::Config;
+use crate::error::Error;
+use console::style;
+use dialoguer::{Input, Select};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
 use std::net::TcpListener;
+use std::time::{SystemTime, UNIX_EPOCH};
 use url::Url;
 
-const CLIENT_ID: &str = "bda57df0ca3244ea96cc8f16dfe04ab7";
+mod store;
+
 const SCOPES: &[&str] = &[
     "playlist-read-private",
     "playlist-read-collaborative",
@@ -12,23 +20,63 @@ const SCOPES: &[&str] = &[
     "user-library-read",
     "user-library-modify",
 ];
-const LOCALHOST: &str = "http://localhost";
-const PORT: u32 = 8000;
-const CALLBACK: &str = "/callback";
-const REDIRECT: &str = "/redirect";
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+/// Address the login callback is received on. Spotify only accepts loopback IP literals in redirect URIs,
+/// and a browser could resolve `localhost` to `::1` where nothing is listening.
+const LOOPBACK: &str = "127.0.0.1";
+/// Tokens expiring in less than that many seconds are considered expired
+const EXPIRY_MARGIN: u64 = 60;
+const DEFAULT_PROFILE: &str = "default";
+
+/// Access token together with the information needed to refresh it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// Unix timestamp (in seconds) after which the access token is no longer valid
+    pub expires_at: u64,
+}
 
-enum AuthStage {
-    /// User will now visit CALLBACK, which should redirect him to REDIRECT
-    ShouldCallback,
-    /// User will now visit REDIRECT, which should return a token
-    ShouldRedirect,
+impl Token {
+    pub fn is_expired(&self) -> bool {
+        unix_now() + EXPIRY_MARGIN >= self.expires_at
+    }
+}
+
+/// Response from the token endpoint, both for code exchange and refresh
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: Option<String>,
+}
+
+impl TokenResponse {
+    fn into_token(self, previous_refresh_token: Option<&String>) -> Token {
+        Token {
+            access_token: self.access_token,
+            // Spotify doesn't always issue a new refresh token, keep using the old one then
+            refresh_token: self
+                .refresh_token
+                .or_else(|| previous_refresh_token.cloned()),
+            expires_at: unix_now() + self.expires_in,
+        }
+    }
+}
+
+/// Body of unsuccessful responses from the token endpoint
+#[derive(Deserialize, Debug)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
 }
 
 enum Response {
-    /// URL contains an access_token in the hash part, redirect the user to REDIRECT with access_token as a query param
+    /// URL contains an authorization code in the query params
     Callback,
-    /// URL contains an access_token in the query params
-    Redirect,
+    /// User denied access to their account
+    Denied,
     BadRequest,
 }
 
@@ -62,7 +110,7 @@ impl Response {
             &format!("HTTP/1.1 {}", status),
             "Server: spotify-tools",
             "Content-Type: text/html; charset=utf-8",
-            &format!("Content-Length: {}", content.as_bytes().len()),
+            &format!("Content-Length: {}", content.len()),
             "",
             &content,
         ]
@@ -72,28 +120,276 @@ impl Response {
 
     fn content(&self) -> Vec<u8> {
         match self {
-            Response::Callback => Response::html_response(&format!("<script>window.location = window.location.origin + '{}?' + window.location.hash.substring(1);</script>", REDIRECT), "200 OK"),
-            Response::Redirect => Response::html_response("Authorization complete. Please return to your terminal. This tab can be closed.", "200 OK"),
-            Response::BadRequest => Response::html_response("Bad Request", "400 Bad Request")
+            Response::Callback => Response::html_response(
+                "Authorization complete. Please return to your terminal. This tab can be closed.",
+                "200 OK",
+            ),
+            Response::Denied => Response::html_response(
+                "Authorization denied. Please return to your terminal.",
+                "200 OK",
+            ),
+            Response::BadRequest => Response::html_response("Bad Request", "400 Bad Request"),
         }
     }
 }
 
-pub fn authorize() -> Result<String, Box<dyn Error>> {
-    let auth_url = format!("https://accounts.spotify.com/authorize?response_type=token&client_id={}&redirect_uri=http://localhost:{}{}&scope={}", CLIENT_ID, PORT, CALLBACK, SCOPES.join(" "));
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
 
-    match open::that(&auth_url) {
-        // Link opened in user's browser
-        Ok(_) => println!("Please check the tab opened in your browser."),
-        // Could not open the link in user's browser
-        Err(_) => println!("Open the following link in your browser:\r\n{}", &auth_url),
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .collect()
+}
+
+/// Code challenge derived from the verifier, as described in RFC 7636 (S256 method)
+fn code_challenge(verifier: &str) -> String {
+    base64::encode_config(
+        &Sha256::digest(verifier.as_bytes()),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
+fn redirect_uri(config: &Config, port: u16) -> String {
+    format!("http://{}:{}{}", LOOPBACK, port, config.callback)
+}
+
+fn bind_listener(config: &Config) -> Result<TcpListener, Error> {
+    // Port 0 lets the system pick any free port
+    let port = if config.auto_port { 0 } else { config.port };
+    TcpListener::bind((LOOPBACK, port)).map_err(|err| match err.kind() {
+        ErrorKind::AddrInUse => Error::Config(format!(
+            "Port {} is already in use. Choose a different one with --port or use --auto-port to pick a free one.",
+            port
+        )),
+        _ => err.into(),
+    })
+}
+
+fn request_token(params: &[(&str, &str)]) -> Result<TokenResponse, Error> {
+    let mut response = reqwest::Client::new().post(TOKEN_URL).form(params).send()?;
+    let body = response.text()?;
+    if !response.status().is_success() {
+        let message = serde_json::from_str::<TokenErrorResponse>(&body)
+            .map(|body| body.error_description.unwrap_or(body.error))
+            .unwrap_or_else(|_| response.status().to_string());
+        return Err(Error::Auth(format!("{}.", message)));
+    }
+    serde_json::from_str(&body).map_err(|source| Error::Decode {
+        endpoint: String::from(TOKEN_URL),
+        source,
+    })
+}
+
+/// Exchange the authorization code received on the callback for a token
+fn exchange_code(
+    config: &Config,
+    code: &str,
+    redirect_uri: &str,
+    verifier: &str,
+) -> Result<Token, Error> {
+    let response = request_token(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", &config.client_id),
+        ("code_verifier", verifier),
+    ])?;
+    Ok(response.into_token(None))
+}
+
+/// Get a new access token using the refresh token, without user interaction
+pub fn refresh(config: &Config, token: &Token) -> Result<Token, Error> {
+    let refresh_token = token
+        .refresh_token
+        .as_ref()
+        .ok_or_else(|| Error::Auth(String::from("No refresh token available.")))?;
+    let response = request_token(&[
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", &config.client_id),
+    ])?;
+    Ok(response.into_token(Some(refresh_token)))
+}
+
+/// Refresh the token if it has expired
+pub fn renew(config: &Config, profile: &str, token: Token) -> Result<Token, Error> {
+    if token.is_expired() {
+        reauthorize(config, profile, &token)
+    } else {
+        Ok(token)
     }
+}
 
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", PORT))?;
+/// Get a new token, falling back to the browser login if it can't be refreshed
+pub fn reauthorize(config: &Config, profile: &str, token: &Token) -> Result<Token, Error> {
+    if config.access_token.is_some() {
+        return Err(Error::Auth(String::from(
+            "The given access token was rejected, it might have expired. Get a new one or log in instead.",
+        )));
+    }
+    let token = refresh(config, token).or_else(|_| {
+        eprintln!("Your session has expired, please log in again.");
+        authorize(config)
+    })?;
+    remember(profile, &token);
+    Ok(token)
+}
 
-    let mut stage = AuthStage::ShouldCallback;
+/// Reuse the token saved by a previous run if possible, only asking the user to log in when needed
+pub fn login(config: &Config, profile: &str) -> Result<Token, Error> {
+    if config.offline {
+        // Nothing is sent, so there is no need for a valid token
+        return Ok(Token {
+            access_token: String::new(),
+            refresh_token: None,
+            expires_at: u64::MAX,
+        });
+    }
+    if let Some(access_token) = &config.access_token {
+        // Not knowing when it expires, the token is used until it gets rejected
+        return Ok(Token {
+            access_token: access_token.to_owned(),
+            refresh_token: None,
+            expires_at: u64::MAX,
+        });
+    }
+    match store::load(profile) {
+        Some(token) => renew(config, profile, token),
+        None => {
+            let token = authorize(config)?;
+            remember(profile, &token);
+            Ok(token)
+        }
+    }
+}
+
+/// Forget the saved token of a profile, the next run will require logging in again
+pub fn logout(profile: &str) -> Result<(), Error> {
+    store::clear(profile)
+}
+
+fn validate_profile(name: &str) -> Result<(), Error> {
+    // Profile names are used as file names
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(Error::Input(format!(
+            "Invalid profile name \"{}\", use only letters, digits, dashes and underscores.",
+            name
+        )))
+    }
+}
+
+/// Choose the profile to use, asking the user when there are multiple ones and none was given.
+/// Without `interactive`, e.g. when running a single command from a script, it has to be given instead.
+pub fn select_profile(config: &Config, interactive: bool) -> Result<String, Error> {
+    if let Some(profile) = &config.profile {
+        validate_profile(profile)?;
+        return Ok(profile.to_owned());
+    }
 
-    Ok(loop {
+    // A given access token doesn't belong to any saved profile
+    if config.access_token.is_some() {
+        return Ok(String::from(DEFAULT_PROFILE));
+    }
+
+    let mut profiles = store::profiles();
+    if profiles.len() <= 1 {
+        return Ok(profiles
+            .pop()
+            .unwrap_or_else(|| String::from(DEFAULT_PROFILE)));
+    }
+    if !interactive {
+        return Err(Error::Input(format!(
+            "Several profiles are saved ({}), choose one with --profile or SPOTIFY_TOOLS_PROFILE.",
+            profiles.join(", ")
+        )));
+    }
+
+    let select = {
+        let mut select = Select::new();
+        select.with_prompt(&style("Select a profile").cyan().to_string());
+        select.items(&profiles);
+        select.item("Add a new profile");
+        select.default(0);
+        select
+    };
+
+    match profiles.get(select.interact()?) {
+        Some(profile) => Ok(profile.to_owned()),
+        None => {
+            let profile = Input::<String>::new()
+                .with_prompt(&style("Name of the new profile").cyan().to_string())
+                .interact()?;
+            validate_profile(&profile)?;
+            Ok(profile)
+        }
+    }
+}
+
+fn remember(profile: &str, token: &Token) {
+    // Failing to save the token is not fatal, the user will just have to log in next time
+    if let Err(err) = store::save(profile, token) {
+        eprintln!("Could not save your credentials: {}", err);
+    }
+}
+
+/// Result of parsing the URL the user got redirected to after the login
+enum Callback {
+    Code(String),
+    Denied(String),
+    /// URL wasn't initiated by this authorization attempt or doesn't contain a code
+    Invalid,
+}
+
+impl Callback {
+    fn from_url(url: &Url, state: &str) -> Callback {
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _v)| k == name)
+                .map(|(_k, v)| v.into_owned())
+        };
+
+        // Ignore callbacks that weren't initiated by this authorization attempt
+        if param("state").as_deref() != Some(state) {
+            return Callback::Invalid;
+        }
+
+        match (param("code"), param("error")) {
+            (Some(code), _) => Callback::Code(code),
+            (None, Some(error)) => Callback::Denied(error),
+            (None, None) => Callback::Invalid,
+        }
+    }
+}
+
+/// Receive the callback on a local server, after opening the login page in user's browser
+fn await_callback(
+    config: &Config,
+    listener: TcpListener,
+    auth_url: &Url,
+    state: &str,
+) -> Result<String, Error> {
+    let port = listener.local_addr()?.port();
+
+    match open::that(auth_url.as_str()) {
+        // Link opened in user's browser
+        Ok(_) => eprintln!("Please check the tab opened in your browser."),
+        // Could not open the link in user's browser
+        Err(_) => eprintln!("Open the following link in your browser:\r\n{}", auth_url),
+    }
+
+    loop {
         // Await a connection
         let (mut socket, _addr) = listener.accept()?;
 
@@ -104,36 +400,123 @@ pub fn authorize() -> Result<String, Box<dyn Error>> {
         };
 
         let req_url = Url::parse(&format!(
-            "{}:{}{}",
-            LOCALHOST,
-            PORT,
+            "http://{}:{}{}",
+            LOOPBACK,
+            port,
             req.split_whitespace().nth(1).unwrap_or("") // Path from the request
-        ))?;
+        ));
 
-        match (&stage, req_url.path()) {
-            (AuthStage::ShouldCallback, CALLBACK) => {
-                // Respond with HTML content that should redirect the user to REDIRECT with a query param containing access_token
+        let req_url = match req_url {
+            Ok(req_url) if req_url.path() == config.callback => req_url,
+            _ => {
+                socket.write_all(&Response::BadRequest.content())?;
+                continue;
+            }
+        };
+
+        match Callback::from_url(&req_url, state) {
+            Callback::Code(code) => {
                 socket.write_all(&Response::Callback.content())?;
-                stage = AuthStage::ShouldRedirect;
+                break Ok(code);
             }
-            (AuthStage::ShouldRedirect, REDIRECT) => {
-                // Got redirected from CALLBACK, query params should contain an access_token
-                match req_url.query_pairs().find(|(k, _v)| k == "access_token") {
-                    Some(token) => {
-                        println!("Authorization successful.");
-                        socket.write_all(&Response::Redirect.content())?;
-                        break String::from(token.1); // Return token from loop
-                    }
-                    None => {
-                        println!("Token not found. Please try again.");
-                        socket.write_all(&Response::BadRequest.content())?;
-                        stage = AuthStage::ShouldCallback;
-                    }
-                };
+            Callback::Denied(error) => {
+                socket.write_all(&Response::Denied.content())?;
+                break Err(Error::Auth(format!("Access was denied ({}).", error)));
             }
-            _ => {
+            Callback::Invalid => {
+                eprintln!("Authorization code not found. Please try again.");
                 socket.write_all(&Response::BadRequest.content())?;
             }
         };
+    }
+}
+
+/// Let the user complete the login on any device and paste the URL they got redirected to
+fn read_callback(auth_url: &Url, state: &str) -> Result<String, Error> {
+    eprintln!(
+        "Open the following link in a browser on any device:\r\n{}",
+        auth_url
+    );
+    eprintln!("After allowing access you will be redirected to a page that may fail to load.");
+    eprintln!("Copy the full address of that page (or just the code from it) and paste it below.");
+
+    loop {
+        let input = Input::<String>::new()
+            .with_prompt(&style("Redirected URL").cyan().to_string())
+            .interact()?;
+        let input = input.trim();
+
+        match parse_pasted_url(input) {
+            Some(url) => match Callback::from_url(&url, state) {
+                Callback::Code(code) => break Ok(code),
+                Callback::Denied(error) => {
+                    break Err(Error::Auth(format!("Access was denied ({}).", error)))
+                }
+                Callback::Invalid => eprintln!("Authorization code not found. Please try again."),
+            },
+            // Not a URL, assume the user pasted just the code
+            None if is_bare_code(input) => break Ok(String::from(input)),
+            None => eprintln!("Authorization code not found. Please try again."),
+        }
+    }
+}
+
+/// Parse the pasted redirect, which may be missing its scheme when copied from the address bar
+fn parse_pasted_url(input: &str) -> Option<Url> {
+    Url::parse(input).ok().or_else(|| {
+        if input.contains("code=") || input.contains('?') {
+            Url::parse(&format!("http://{}", input)).ok()
+        } else {
+            None
+        }
     })
 }
+
+/// Authorization codes only contain URL-safe characters, anything else is a mangled paste
+fn is_bare_code(input: &str) -> bool {
+    !input.is_empty()
+        && input
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.~".contains(c))
+}
+
+pub fn authorize(config: &Config) -> Result<Token, Error> {
+    let verifier = random_string(64);
+    let state = random_string(16);
+
+    // In headless mode nothing listens for the callback, the user pastes the URL instead
+    let listener = if config.headless {
+        None
+    } else {
+        Some(bind_listener(config)?)
+    };
+    // The port has to be known before building the redirect URI
+    let port = match &listener {
+        Some(listener) => listener.local_addr()?.port(),
+        None => config.port,
+    };
+    let redirect_uri = redirect_uri(config, port);
+
+    let auth_url = Url::parse_with_params(
+        AUTHORIZE_URL,
+        &[
+            ("response_type", "code"),
+            ("client_id", &config.client_id),
+            ("redirect_uri", &redirect_uri),
+            ("scope", &SCOPES.join(" ")),
+            ("code_challenge_method", "S256"),
+            ("code_challenge", &code_challenge(&verifier)),
+            ("state", &state),
+        ],
+    )
+    .map_err(|err| Error::Config(format!("Invalid authorization URL: {}.", err)))?;
+
+    let code = match listener {
+        Some(listener) => await_callback(config, listener, &auth_url, &state)?,
+        None => read_callback(&auth_url, &state)?,
+    };
+
+    let token = exchange_code(config, &code, &redirect_uri, &verifier)?;
+    eprintln!("Authorization successful.");
+    Ok(token)
+}
@@ -0,0 +1,112 @@
+//! Encrypted on-disk storage of the tokens, allowing them to be reused across runs.
+//! Each profile has its own token, all of them are encrypted with the same key.
+//! The encryption key is kept in the config directory, separately from the tokens themselves.
+use super::Token;
+use crate::config;
+use crate::error::Error;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, OsRng},
+    AeadCore, ChaCha20Poly1305, Key, Nonce,
+};
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const PROFILES_DIR: &str = "profiles";
+const TOKEN_EXTENSION: &str = "token";
+const KEY_FILE: &str = "token.key";
+const NONCE_LEN: usize = 12;
+
+fn profiles_dir() -> Result<PathBuf, Error> {
+    Ok(config::cache_dir()?.join(PROFILES_DIR))
+}
+
+fn token_path(profile: &str) -> Result<PathBuf, Error> {
+    Ok(profiles_dir()?
+        .join(profile)
+        .with_extension(TOKEN_EXTENSION))
+}
+
+fn key_path() -> Result<PathBuf, Error> {
+    Ok(config::config_dir()?.join(KEY_FILE))
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(data)?;
+    Ok(())
+}
+
+fn cipher(create_key: bool) -> Result<ChaCha20Poly1305, Error> {
+    let path = key_path()?;
+    let key = match fs::read(&path) {
+        Ok(key) if key.len() == 32 => *Key::from_slice(&key),
+        _ if create_key => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            write_private(&path, &key)?;
+            key
+        }
+        _ => return Err(Error::Io(ErrorKind::NotFound.into())),
+    };
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+/// Names of all profiles with a saved token, sorted alphabetically
+pub fn profiles() -> Vec<String> {
+    let entries = match profiles_dir().and_then(|dir| Ok(fs::read_dir(dir)?)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut profiles = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some(OsStr::new(TOKEN_EXTENSION)))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+        .collect::<Vec<_>>();
+    profiles.sort();
+    profiles
+}
+
+/// Load the saved token of a profile, returns None if there is none or it can't be decrypted
+pub fn load(profile: &str) -> Option<Token> {
+    let data = fs::read(token_path(profile).ok()?).ok()?;
+    if data.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plaintext = cipher(false)
+        .ok()?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .ok()?;
+    serde_json::from_slice(&plaintext).ok()
+}
+
+pub fn save(profile: &str, token: &Token) -> Result<(), Error> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(true)?
+        .encrypt(&nonce, serde_json::to_vec(token)?.as_ref())
+        .map_err(|_| io::Error::other("Could not encrypt the token."))?;
+    write_private(
+        &token_path(profile)?,
+        &[nonce.as_slice(), &ciphertext].concat(),
+    )
+}
+
+/// Remove the saved token of a profile
+pub fn clear(profile: &str) -> Result<(), Error> {
+    let path = token_path(profile)?;
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+//! Copy of the library saved on disk, so that it doesn't have to be downloaded by every command.
+//! Playlists are reused as long as their snapshot ID is unchanged, liked songs are updated with
+//! the ones added since the last run. In offline mode everything is read from it.
+use crate::api::models::{FullArtist, PlaylistTrack, SavedTrack, SimplifiedPlaylist, User};
+use crate::config;
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const CACHE_DIR: &str = "library";
+/// Bumped whenever the cached models change, older caches are then ignored
+const CACHE_VERSION: u32 = 6;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+    version: u32,
+    /// Logged in user, as of the last run
+    pub user: Option<User>,
+    /// Newest first, as returned by the API
+    pub saved_tracks: Vec<SavedTrack>,
+    /// Playlists followed by the user, None until they were listed once
+    pub followed_playlists: Option<Vec<SimplifiedPlaylist>>,
+    /// Tracks of the playlists, keyed by playlist ID
+    pub playlists: HashMap<String, CachedPlaylist>,
+    /// Keyed by artist ID, for their genres
+    pub artists: HashMap<String, FullArtist>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CachedPlaylist {
+    pub snapshot_id: String,
+    pub tracks: Vec<PlaylistTrack>,
+}
+
+fn cache_path(profile: &str) -> Result<PathBuf, Error> {
+    Ok(config::cache_dir()?
+        .join(CACHE_DIR)
+        .join(profile)
+        .with_extension("json"))
+}
+
+impl Cache {
+    /// Load the cache of a profile, it's empty if there is none or it's unreadable
+    pub fn load(profile: &str) -> Cache {
+        cache_path(profile)
+            .ok()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|data| serde_json::from_slice::<Cache>(&data).ok())
+            .filter(|cache| cache.version == CACHE_VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&mut self, profile: &str) -> Result<(), Error> {
+        let path = cache_path(profile)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.version = CACHE_VERSION;
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Remove the cache of a profile
+    pub fn clear(profile: &str) -> Result<(), Error> {
+        let path = cache_path(profile)?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Cached tracks of a playlist, if it hasn't changed since they were saved
+    pub fn playlist(&self, id: &str, snapshot_id: &str) -> Option<&[PlaylistTrack]> {
+        self.playlists
+            .get(id)
+            .filter(|playlist| playlist.snapshot_id == snapshot_id)
+            .map(|playlist| &playlist.tracks[..])
+    }
+}
@@ -0,0 +1,97 @@
+use clap::builder::FalseyValueParser;
+use clap::Parser;
+use spotify_tools::cmds::Cmd;
+use spotify_tools::config::{Config, Output, Settings};
+use spotify_tools::error::Error;
+use std::path::PathBuf;
+
+/// A set of tools for manipulating your Spotify library.
+/// Without a command, an interactive menu is shown.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Cmd>,
+
+    /// Path to the config file [default: <config dir>/spotify-tools/config.json]
+    #[arg(long, env = "SPOTIFY_TOOLS_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Client ID of the Spotify application used for logging in
+    #[arg(long, env = "SPOTIFY_TOOLS_CLIENT_ID")]
+    pub client_id: Option<String>,
+
+    /// Local port on which the login callback is received [default: 8000]
+    #[arg(long, env = "SPOTIFY_TOOLS_PORT")]
+    pub port: Option<u16>,
+
+    /// Path of the login callback, has to match the redirect URI of the Spotify application [default: /callback]
+    #[arg(long, env = "SPOTIFY_TOOLS_CALLBACK")]
+    pub callback: Option<String>,
+
+    /// Let the system pick a free port for the login callback
+    #[arg(long, env = "SPOTIFY_TOOLS_AUTO_PORT", value_parser = FalseyValueParser::new())]
+    pub auto_port: bool,
+
+    /// Name of the account profile to use, each profile is logged in separately
+    #[arg(long, env = "SPOTIFY_TOOLS_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Base URL of the Spotify Web API [default: https://api.spotify.com/v1]
+    #[arg(long, env = "SPOTIFY_TOOLS_API_URL")]
+    pub api_url: Option<String>,
+
+    /// Access token to use instead of logging in, e.g. one obtained by another tool
+    #[arg(long, env = "SPOTIFY_TOOLS_ACCESS_TOKEN", hide_env_values = true)]
+    pub access_token: Option<String>,
+
+    /// Format of the results, with JSON only the results are printed to stdout
+    #[arg(long, value_enum, default_value_t = Output::Text, global = true)]
+    pub output: Output,
+
+    /// Only print the changes that would be made to your library, without making them
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Use only the cached library, without connecting to Spotify. Changes are queued until `sync` is run
+    #[arg(long, env = "SPOTIFY_TOOLS_OFFLINE", value_parser = FalseyValueParser::new(), global = true)]
+    pub offline: bool,
+
+    /// Download the whole library instead of reusing the copy cached by previous runs
+    #[arg(long, env = "SPOTIFY_TOOLS_NO_CACHE", value_parser = FalseyValueParser::new(), global = true)]
+    pub no_cache: bool,
+
+    /// Log in without a local browser, by pasting the URL you got redirected to
+    #[arg(long, env = "SPOTIFY_TOOLS_HEADLESS", value_parser = FalseyValueParser::new())]
+    pub headless: bool,
+}
+
+impl Args {
+    /// Settings from the flags, environment variables are already handled by clap as a fallback for them
+    pub fn config(&self) -> Result<Config, Error> {
+        let mut config = Config::load(
+            self.config.as_deref(),
+            Settings {
+                client_id: self.client_id.clone(),
+                port: self.port,
+                callback: self.callback.clone(),
+                // An absent flag doesn't override the config file
+                auto_port: Some(true).filter(|_| self.auto_port),
+                headless: Some(true).filter(|_| self.headless),
+                profile: self.profile.clone(),
+                api_url: self.api_url.clone(),
+                cache: Some(false).filter(|_| self.no_cache),
+            },
+        )?;
+        config.access_token = self.access_token.clone();
+        if self.offline && !config.cache {
+            return Err(Error::Config(String::from(
+                "Offline mode needs the cache, it can't be disabled.",
+            )));
+        }
+        config.offline = self.offline;
+        config.dry_run = self.dry_run;
+        config.output = self.output;
+        Ok(config)
+    }
+}
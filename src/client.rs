@@ -0,0 +1,641 @@
+//! Authorized access to the Spotify Web API, shared by all the commands.
+use crate::api::{
+    endpoints::{ALL_PLAYLISTS, ARTISTS_INFO, GET_USER, SAVED_TRACKS},
+    models::{
+        ArtistsResponse, ErrorResponse, FullArtist, Paging, PlaylistTrack, SavedTrack,
+        SimplifiedPlaylist, User,
+    },
+};
+use crate::auth::{self, Token};
+use crate::cache::{Cache, CachedPlaylist};
+use crate::config::{Config, Output};
+use crate::error::Error;
+use crate::queue::{Change, Queue};
+use crate::ui::Ui;
+use console::style;
+use itertools::Itertools;
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Client as HttpClient, Method, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Parts of the playlist items used by the models, the rest is left out of the responses
+const PLAYLIST_ITEM_FIELDS: &str = "items(added_at,added_by.id,track(type,is_local,name,id,uri,duration_ms,\
+    popularity,explicit,external_ids.isrc,disc_number,track_number,is_playable,linked_from(id,uri),\
+    artists(id,name),album(id,name,album_type,release_date,release_date_precision,total_tracks,images),\
+    show.name)),limit,next,total";
+
+/// Maximum number of times a single request is retried after a rate limit or a transient error
+const MAX_RETRIES: u32 = 5;
+/// Delay before the first retry, doubled on each following one
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+/// Longest `Retry-After` worth waiting for, above that the rate limit is reported as an error
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+/// Number of pages of a paged endpoint fetched at once
+const PAGE_WORKERS: usize = 4;
+
+/// Exponential backoff with jitter, so parallel clients don't all retry at the same moment
+fn backoff(retries: u32) -> Duration {
+    let delay = BACKOFF_BASE * 2u32.pow(retries);
+    delay / 2 + delay.mul_f64(rand::thread_rng().gen_range(0.0, 0.5))
+}
+
+/// Delay requested by a rate limited response, None if it's too long to wait for
+fn retry_after(response: &Response, retries: u32) -> Option<Duration> {
+    let delay = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        // The header is in whole seconds, wait a bit longer so the window is over for sure
+        .map_or_else(|| backoff(retries), |secs| Duration::from_secs(secs + 1));
+    if delay <= MAX_RETRY_AFTER {
+        Some(delay)
+    } else {
+        None
+    }
+}
+
+fn parse_url(url: &str) -> Result<Url, Error> {
+    Url::parse(url).map_err(|err| Error::Config(format!("Invalid API URL {}: {}", url, err)))
+}
+
+/// URL of the page at `offset`, keeping the other query parameters of the first page
+fn page_url(url: &str, offset: u64, limit: u64) -> Result<String, Error> {
+    let mut url = parse_url(url)?;
+    let query = url
+        .query_pairs()
+        .filter(|(key, _)| key != "offset" && key != "limit")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(&query)
+        .append_pair("offset", &offset.to_string())
+        .append_pair("limit", &limit.to_string());
+    Ok(String::from(url.as_str()))
+}
+
+fn not_cached(what: &str) -> Error {
+    Error::Offline(format!(
+        "{} not cached yet, run the command online first or refresh the cache.",
+        what
+    ))
+}
+
+/// Path of the URL, used to tell which endpoint a request was made to in error messages
+fn endpoint(url: &str) -> String {
+    Url::parse(url).map_or_else(|_| String::from(url), |url| String::from(url.path()))
+}
+
+/// Deserialize the body of a successful response
+pub fn decode<T: DeserializeOwned>(url: &str, mut response: Response) -> Result<T, Error> {
+    serde_json::from_str(&response.text()?).map_err(|source| Error::Decode {
+        endpoint: endpoint(url),
+        source,
+    })
+}
+
+/// Client of the Web API for a single profile, renewing its token when needed.
+/// Progress and status messages are reported through the given UI.
+/// It can be shared between threads, requests are then rate limited together.
+pub struct Client {
+    http: HttpClient,
+    config: Config,
+    profile: String,
+    token: Mutex<Token>,
+    /// Set when Spotify asks to slow down, no request is sent before that time
+    paused_until: Mutex<Option<Instant>>,
+    /// Loaded from disk on first use
+    cache: Mutex<Option<Cache>>,
+    ui: Box<dyn Ui>,
+}
+
+impl Client {
+    pub fn new(config: Config, profile: String, token: Token, ui: Box<dyn Ui>) -> Client {
+        Client {
+            http: HttpClient::new(),
+            config,
+            profile,
+            token: Mutex::new(token),
+            paused_until: Mutex::new(None),
+            cache: Mutex::new(None),
+            ui,
+        }
+    }
+
+    pub fn ui(&self) -> &dyn Ui {
+        &*self.ui
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn profile(&self) -> &str {
+        &self.profile
+    }
+
+    pub fn current_user(&self) -> Result<User, Error> {
+        if self.config.offline {
+            return self
+                .with_cache(|cache| cache.user.clone())
+                .ok_or_else(|| not_cached("Your account is"));
+        }
+
+        let user = self.get::<User>(GET_USER)?;
+        if self.config.cache {
+            let changed = self.with_cache(|cache| {
+                let changed = cache.user.as_ref() != Some(&user);
+                cache.user = Some(user.clone());
+                changed
+            });
+            if changed {
+                self.save_cache();
+            }
+        }
+        Ok(user)
+    }
+
+    /// Liked songs, newest first.
+    /// With the cache enabled, only the songs added since the last run are downloaded.
+    pub fn saved_tracks(&self) -> Result<Vec<SavedTrack>, Error> {
+        if self.config.offline {
+            let cached = self.with_cache(|cache| cache.saved_tracks.clone());
+            return if cached.is_empty() {
+                Err(not_cached("Your liked songs are"))
+            } else {
+                Ok(cached)
+            };
+        }
+        if !self.config.cache {
+            return self.paged_request(SAVED_TRACKS);
+        }
+
+        let cached = self.with_cache(|cache| cache.saved_tracks.clone());
+        let tracks = if cached.is_empty() {
+            self.paged_request(SAVED_TRACKS)?
+        } else {
+            self.update_saved_tracks(cached)?
+        };
+        self.with_cache(|cache| cache.saved_tracks = tracks.clone());
+        self.save_cache();
+        Ok(tracks)
+    }
+
+    /// Download the newest liked songs until reaching one that is already cached
+    fn update_saved_tracks(&self, cached: Vec<SavedTrack>) -> Result<Vec<SavedTrack>, Error> {
+        let known = cached
+            .iter()
+            .map(|saved| (saved.track.id.clone(), saved.added_at.clone()))
+            .collect::<HashSet<_>>();
+        let mut tracks = Vec::new();
+        let mut next_url = Some(String::from(SAVED_TRACKS));
+        while let Some(url) = next_url {
+            let page = self.get::<Paging<SavedTrack>>(&url)?;
+            let position = page.items.iter().position(|saved| {
+                known.contains(&(saved.track.id.clone(), saved.added_at.clone()))
+            });
+            match position {
+                Some(position) => {
+                    tracks.extend(page.items.into_iter().take(position));
+                    // Songs unliked since the last run make the total differ, everything is then downloaded again
+                    return if (tracks.len() + cached.len()) as u64 == page.total {
+                        tracks.extend(cached);
+                        Ok(tracks)
+                    } else {
+                        self.paged_request(SAVED_TRACKS)
+                    };
+                }
+                None => {
+                    tracks.extend(page.items);
+                    next_url = page.next;
+                }
+            }
+        }
+        // None of the cached songs are liked anymore, all the current ones were downloaded anyway
+        Ok(tracks)
+    }
+
+    /// Playlists followed by the user, including the ones they don't own
+    pub fn playlists(&self) -> Result<Vec<SimplifiedPlaylist>, Error> {
+        if self.config.offline {
+            return self
+                .with_cache(|cache| cache.followed_playlists.clone())
+                .ok_or_else(|| not_cached("Your playlists are"));
+        }
+
+        let playlists = self.paged_request::<SimplifiedPlaylist>(ALL_PLAYLISTS)?;
+        if self.config.cache {
+            let ids = playlists
+                .iter()
+                .map(|playlist| &playlist.id)
+                .collect::<HashSet<_>>();
+            let changed = self.with_cache(|cache| {
+                let changed = cache.followed_playlists.as_ref() != Some(&playlists);
+                cache.followed_playlists = Some(playlists.clone());
+                // Forget the tracks of the playlists the user doesn't follow anymore
+                cache.playlists.retain(|id, _| ids.contains(id));
+                changed
+            });
+            if changed {
+                self.save_cache();
+            }
+        }
+        Ok(playlists)
+    }
+
+    /// Tracks of a playlist, the cached ones are reused while the playlist is unchanged
+    pub fn playlist_tracks(
+        &self,
+        playlist: &SimplifiedPlaylist,
+    ) -> Result<Vec<PlaylistTrack>, Error> {
+        if self.config.offline {
+            // Whatever was cached is the best there is, even if the playlist changed since
+            return self
+                .with_cache(|cache| {
+                    cache
+                        .playlists
+                        .get(&playlist.id)
+                        .map(|cached| cached.tracks.clone())
+                })
+                .ok_or_else(|| not_cached(&format!("The tracks of \"{}\" are", playlist.name)));
+        }
+        if self.config.cache {
+            let cached = self.with_cache(|cache| {
+                cache
+                    .playlist(&playlist.id, &playlist.snapshot_id)
+                    .map(<[_]>::to_vec)
+            });
+            if let Some(tracks) = cached {
+                return Ok(tracks);
+            }
+        }
+
+        // Without asking for episodes, they are returned in the shape of tracks
+        let mut url = parse_url(&playlist.tracks.href)?;
+        url.query_pairs_mut()
+            .append_pair("additional_types", "track,episode")
+            .append_pair("market", "from_token")
+            .append_pair("fields", PLAYLIST_ITEM_FIELDS);
+        let tracks = self.paged_request::<PlaylistTrack>(url.as_str())?;
+        if self.config.cache {
+            self.with_cache(|cache| {
+                cache.playlists.insert(
+                    playlist.id.clone(),
+                    CachedPlaylist {
+                        snapshot_id: playlist.snapshot_id.clone(),
+                        tracks: tracks.clone(),
+                    },
+                )
+            });
+            self.save_cache();
+        }
+        Ok(tracks)
+    }
+
+    /// Forget the cached library of the profile, it's downloaded again when next needed
+    pub fn clear_cache(&self) -> Result<(), Error> {
+        Cache::clear(&self.profile)?;
+        // The user was just fetched when starting, keep it instead of asking again
+        let mut cache = self.cache.lock().unwrap();
+        let mut cleared = Cache::default();
+        cleared.user = cache.take().and_then(|cache| cache.user);
+        *cache = Some(cleared);
+        Ok(())
+    }
+
+    fn with_cache<R>(&self, f: impl FnOnce(&mut Cache) -> R) -> R {
+        let mut cache = self.cache.lock().unwrap();
+        f(cache.get_or_insert_with(|| Cache::load(&self.profile)))
+    }
+
+    fn save_cache(&self) {
+        // Not fatal, the next run will only be slower
+        if let Err(err) = self.with_cache(|cache| cache.save(&self.profile)) {
+            self.info(format!("Could not save the library cache: {}", err));
+        }
+    }
+
+    /// Full information about the given artists, fetched in batches of 50.
+    /// With the cache enabled, only the artists not cached yet are downloaded.
+    pub fn artists(&self, ids: &[&str]) -> Result<Vec<FullArtist>, Error> {
+        if self.config.offline {
+            return self.with_cache(|cache| {
+                ids.iter()
+                    .map(|id| cache.artists.get(*id).cloned())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| not_cached("The genres of some artists are"))
+            });
+        }
+
+        let mut artists = if self.config.cache {
+            self.with_cache(|cache| {
+                ids.iter()
+                    .filter_map(|id| Some((id.to_string(), cache.artists.get(*id)?.clone())))
+                    .collect::<HashMap<_, _>>()
+            })
+        } else {
+            HashMap::new()
+        };
+        let missing = ids
+            .iter()
+            .filter(|id| !artists.contains_key(**id))
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            let progress = self.ui.progress(missing.len() as u64);
+            let mut fetched = Vec::with_capacity(missing.len());
+            for chunk in missing.chunks(50) {
+                let data = self.get::<ArtistsResponse>(&format!(
+                    "{}?ids={}",
+                    ARTISTS_INFO,
+                    chunk.iter().join(",")
+                ))?;
+                progress.inc(chunk.len() as u64);
+                fetched.extend(data.artists);
+            }
+            progress.finish();
+
+            if self.config.cache {
+                self.with_cache(|cache| {
+                    for artist in &fetched {
+                        cache.artists.insert(artist.id.clone(), artist.clone());
+                    }
+                });
+                self.save_cache();
+            }
+            artists.extend(
+                fetched
+                    .into_iter()
+                    .map(|artist| (artist.id.clone(), artist)),
+            );
+        }
+
+        // Back in the order of the given IDs
+        Ok(ids.iter().filter_map(|id| artists.remove(*id)).collect())
+    }
+
+    /// Fetch all the items of a paged endpoint.
+    /// The first page tells how many items there are, the other pages are then fetched by offset,
+    /// `PAGE_WORKERS` at a time, and put back in order.
+    pub fn paged_request<T: DeserializeOwned + Send>(
+        &self,
+        endpoint: &str,
+    ) -> Result<Vec<T>, Error> {
+        let first = self.get::<Paging<T>>(endpoint)?;
+        let progress = self.ui.progress(first.total);
+        progress.inc(first.items.len() as u64);
+        if first.next.is_none() || first.limit == 0 {
+            progress.finish();
+            return Ok(first.items);
+        }
+
+        let url = self.api_url(endpoint);
+        let limit = first.limit;
+        let offsets = (limit..first.total)
+            .step_by(limit as usize)
+            .collect::<Vec<_>>();
+        let next_page = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let fetch_pages = || {
+            let mut pages = Vec::new();
+            loop {
+                let index = next_page.fetch_add(1, Ordering::SeqCst);
+                // Stop early when another worker failed, its error is returned anyway
+                if index >= offsets.len() || failed.load(Ordering::SeqCst) {
+                    break Ok(pages);
+                }
+                let page = page_url(&url, offsets[index], limit)
+                    .and_then(|page_url| self.get::<Paging<T>>(&page_url));
+                match page {
+                    Ok(page) => {
+                        progress.inc(page.items.len() as u64);
+                        pages.push((index, page.items));
+                    }
+                    Err(err) => {
+                        failed.store(true, Ordering::SeqCst);
+                        break Err(err);
+                    }
+                }
+            }
+        };
+        let results = thread::scope(|scope| {
+            let workers = (0..min(PAGE_WORKERS, offsets.len()))
+                .map(|_| scope.spawn(fetch_pages))
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        progress.finish();
+
+        let mut pages = Vec::with_capacity(offsets.len());
+        for result in results {
+            pages.extend(result?);
+        }
+        pages.sort_by_key(|(index, _)| *index);
+
+        let mut data = first.items;
+        data.extend(pages.into_iter().flat_map(|(_, items)| items));
+        Ok(data)
+    }
+
+    pub fn json_output(&self) -> bool {
+        self.config.output == Output::Json
+    }
+
+    /// Show a status message
+    pub fn info(&self, message: impl fmt::Display) {
+        self.ui.message(&message.to_string());
+    }
+
+    /// Send a request authorized with the current token.
+    /// If the token gets rejected, the user is re-authenticated and the request is retried once.
+    /// Rate limited requests are retried after the delay asked for by Spotify, server and network
+    /// errors of reads with an exponential backoff, up to `MAX_RETRIES` times in total.
+    pub fn send(&self, method: Method, url: &str, body: Option<&Value>) -> Result<Response, Error> {
+        let url = &self.api_url(url);
+        if self.config.offline {
+            return Err(Error::Offline(format!(
+                "{} can't be reached offline.",
+                endpoint(url)
+            )));
+        }
+        let token = self.token.lock().unwrap().clone();
+        if token.is_expired() {
+            self.reauthenticate(&token)?;
+        }
+
+        let mut reauthenticated = false;
+        let mut retries = 0;
+        loop {
+            self.wait_for_pause();
+            let token = self.token.lock().unwrap().clone();
+            let mut request = self
+                .http
+                .request(method.clone(), url)
+                .bearer_auth(&token.access_token);
+            if let Some(body) = body {
+                request = request.json(body);
+            }
+
+            let mut response = match request.send() {
+                Ok(response) => response,
+                // A modification might have been applied before the connection broke, only reads are safe to repeat
+                Err(_) if method == Method::GET && retries < MAX_RETRIES => {
+                    self.wait(backoff(retries));
+                    retries += 1;
+                    continue;
+                }
+                Err(err) => break Err(err.into()),
+            };
+            let status = response.status();
+            if status == StatusCode::UNAUTHORIZED && !reauthenticated {
+                // Token got revoked or expired in the meantime, get a new one and try again
+                self.reauthenticate(&token)?;
+                reauthenticated = true;
+                continue;
+            }
+            if retries < MAX_RETRIES {
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    if let Some(delay) = retry_after(&response, retries) {
+                        // The limit applies to all requests, not just this one
+                        self.pause(delay);
+                        retries += 1;
+                        continue;
+                    }
+                // Like after a network error, a modification might have been applied already.
+                // Rate limited requests are never applied, those are safe to repeat.
+                } else if status.is_server_error() && method == Method::GET {
+                    self.wait(backoff(retries));
+                    retries += 1;
+                    continue;
+                }
+            }
+            if status.is_client_error() || status.is_server_error() {
+                let message = response
+                    .text()
+                    .ok()
+                    .and_then(|body| serde_json::from_str::<ErrorResponse>(&body).ok())
+                    .map(|body| body.error.message);
+                break Err(Error::Http {
+                    status,
+                    endpoint: endpoint(url),
+                    message,
+                });
+            }
+            break Ok(response);
+        }
+    }
+
+    /// Send a request modifying the user's library.
+    /// On a dry run the request is only printed and None is returned instead of the response.
+    /// In offline mode it's queued to be sent by `sync`, unless it already is, and None is returned as well.
+    pub fn modify(
+        &self,
+        method: Method,
+        url: &str,
+        body: &Value,
+    ) -> Result<Option<Response>, Error> {
+        if self.config.dry_run {
+            self.info(format!(
+                "{} {} {}",
+                style("[dry run]").yellow(),
+                method,
+                self.api_url(url)
+            ));
+            self.info(serde_json::to_string_pretty(body)?);
+            Ok(None)
+        } else if self.config.offline {
+            let mut queue = Queue::load(&self.profile)?;
+            let change = Change::new(&method, url, body);
+            // Sent twice, it would be applied twice
+            let status = if !queue.contains(&change) {
+                queue.changes.push(change);
+                queue.save(&self.profile)?;
+                "[queued]"
+            } else if change.creates_playlist() {
+                // The songs added to it would be queued again, for a second playlist
+                return Err(Error::Offline(format!(
+                    "The playlist \"{}\" is already queued to be created, run `sync` first.",
+                    body["name"].as_str().unwrap_or_default()
+                )));
+            } else {
+                "[already queued]"
+            };
+            self.info(format!(
+                "{} {} {}",
+                style(status).yellow(),
+                method,
+                self.api_url(url)
+            ));
+            Ok(None)
+        } else {
+            Ok(Some(self.send(method, url, Some(body))?))
+        }
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        decode(url, self.send(Method::GET, url, None)?)
+    }
+
+    /// Resolve an endpoint path against the API base URL, absolute URLs are kept as they are
+    pub fn api_url(&self, endpoint: &str) -> String {
+        if endpoint.starts_with('/') {
+            format!("{}{}", self.config.api_url, endpoint)
+        } else {
+            String::from(endpoint)
+        }
+    }
+
+    /// Pause before retrying a request, telling the user when it's going to take a while
+    fn wait(&self, delay: Duration) {
+        self.announce_delay(delay);
+        thread::sleep(delay);
+    }
+
+    /// Hold off all requests for the given time
+    fn pause(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|current| current < until) {
+            self.announce_delay(delay);
+            *paused_until = Some(until);
+        }
+    }
+
+    fn wait_for_pause(&self) {
+        let paused_until = *self.paused_until.lock().unwrap();
+        if let Some(until) = paused_until {
+            thread::sleep(until.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    fn announce_delay(&self, delay: Duration) {
+        if delay.as_secs() >= 5 {
+            self.info(format!(
+                "Spotify is busy, retrying in {} seconds...",
+                delay.as_secs()
+            ));
+        }
+    }
+
+    /// Replace the rejected token, unless another request already did it in the meantime
+    fn reauthenticate(&self, rejected: &Token) -> Result<(), Error> {
+        let mut token = self.token.lock().unwrap();
+        if token.access_token == rejected.access_token {
+            *token = auth::reauthorize(&self.config, &self.profile, &token)?;
+        }
+        Ok(())
+    }
+}
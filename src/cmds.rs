@@ -1,31 +1,59 @@
-use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION},
-    Client,
-};
-use std::error::Error;
+//! Commands of the CLI, prompting the user for whatever wasn't given as an option.
+use crate::client::Client;
+use crate::error::Error;
+use clap::Subcommand;
 
 mod decades;
 mod duplicates;
 mod genres;
-mod spotify_api;
+mod mirror;
+mod query;
+mod refresh_cache;
+mod sync;
 mod tracks_info;
 mod util;
 
+pub use decades::DecadesOptions;
+pub use duplicates::DuplicatesOptions;
+pub use genres::GenresOptions;
+pub use mirror::DatabaseOptions;
+pub use query::QueryOptions;
+pub use util::PlaylistOptions;
+
+#[derive(Subcommand, Debug)]
+pub enum Cmd {
+    /// Show information about top artists from your library
+    TracksInfo,
+    /// Remove duplicates from liked songs, from a playlist or across all of them
+    Duplicates(DuplicatesOptions),
+    /// Categorize your liked songs based on their release decade
+    Decades(DecadesOptions),
+    /// Categorize your liked songs based on their artist's genre
+    Genres(GenresOptions),
+    /// Mirror your library into a local SQLite database
+    Mirror(DatabaseOptions),
+    /// Run an SQL query against the mirrored library
+    Query(QueryOptions),
+    /// Download your library again, replacing the cached copy
+    RefreshCache,
+    /// Send the changes made to your library in offline mode
+    Sync,
+    /// Log out and forget saved credentials
+    Logout,
+}
+
 pub struct CmdHandler {
     client: Client,
+    /// Display name of the logged in user, or their ID if they don't have one
+    user_name: String,
 }
 
 impl CmdHandler {
-    pub fn new(token: &str) -> Result<CmdHandler, Box<dyn Error>> {
-        let mut headers = HeaderMap::new();
-        // Set auth header
-        headers.insert(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", token))?,
-        );
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()?;
-        Ok(CmdHandler { client })
+    pub fn new(client: Client) -> Result<CmdHandler, Error> {
+        let user = client.current_user()?;
+        Ok(CmdHandler {
+            client,
+            user_name: user.display_name.unwrap_or(user.id),
+        })
     }
 }
@@ -1,73 +1,88 @@
-use super::spotify_api::{
-    endpoints::SAVED_TRACKS,
-    models::{SavedTrack, Track},
-};
+use super::util::PlaylistOptions;
 use super::CmdHandler;
-use console::style;
-use dialoguer::Checkboxes;
+use crate::analysis;
+use crate::error::Error;
+use clap::Args;
 use itertools::Itertools;
-use std::collections::HashMap;
-use std::error::Error;
+use serde::Serialize;
 
-impl CmdHandler {
-    pub fn decades(&self) -> Result<(), Box<dyn Error>> {
-        println!("Loading your library information...");
-        let saved_tracks = self.paged_request::<SavedTrack>(SAVED_TRACKS)?;
-        println!("Library loaded.");
+#[derive(Args, Debug, Default)]
+pub struct DecadesOptions {
+    /// Decades to create the playlist from, e.g. 1980s,1990s
+    #[arg(long, value_delimiter = ',')]
+    pub select: Option<Vec<String>>,
 
-        let mut decade_map = HashMap::new();
+    #[command(flatten)]
+    pub playlist: PlaylistOptions,
+}
 
-        for saved_track in &saved_tracks {
-            let track = &saved_track.track;
-            let decade = format!("{}0s", &track.album.release_date[0..3]);
-            decade_map
-                .entry(decade)
-                .and_modify(|v: &mut Vec<&Track>| v.push(track))
-                .or_insert_with(|| vec![track]);
-        }
+#[derive(Serialize)]
+struct Decade<'a> {
+    decade: &'a str,
+    tracks: Vec<&'a str>,
+}
+
+impl CmdHandler {
+    pub fn decades(&self, options: &DecadesOptions) -> Result<(), Error> {
+        self.client.info("Loading your library information...");
+        let saved_tracks = self.client.saved_tracks()?;
+        self.client.info("Library loaded.");
 
-        let tracks_by_decades = decade_map
-            .into_iter()
-            .sorted_by(|(k1, _), (k2, _)| k1.cmp(&k2))
-            .collect::<Vec<_>>();
+        let tracks_by_decades =
+            analysis::group_by_decade(saved_tracks.iter().map(|saved| &saved.track));
 
-        let checkboxes = {
-            let mut checkboxes = Checkboxes::new();
-            checkboxes.with_prompt(
-                &style("Select decades to create your playlist from")
-                    .cyan()
-                    .to_string(),
-            );
-            checkboxes.items(
+        if self.client.json_output() {
+            self.print_json(
                 &tracks_by_decades
                     .iter()
-                    .map(|(k, v)| format!("{} - {} songs", &k[2..], v.len()))
-                    .collect::<Vec<String>>()
+                    .map(|decade| Decade {
+                        decade: &decade.decade,
+                        tracks: decade.tracks.iter().map(|track| &track.uri[..]).collect(),
+                    })
+                    .collect::<Vec<_>>(),
+            )?;
+            // With JSON output a playlist is only created when the decades are given explicitly
+            if options.select.is_none() {
+                return Ok(());
+            }
+        }
+
+        let selection = match &options.select {
+            Some(decades) => decades
+                .iter()
+                .map(|decade| {
+                    // Both the full (1980s) and the short (80s) form are accepted
+                    tracks_by_decades
+                        .iter()
+                        .position(|d| &d.decade == decade || &d.decade[2..] == decade)
+                        .ok_or_else(|| Error::Input(format!("No songs from the {} found.", decade)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => self.client.ui().multi_select(
+                "Select decades to create your playlist from",
+                &tracks_by_decades
                     .iter()
-                    .map(|s| s.as_ref())
-                    .collect::<Vec<&str>>()[..],
-            );
-            checkboxes.paged(true);
-            checkboxes
+                    .map(|decade| {
+                        format!("{} - {} songs", &decade.decade[2..], decade.tracks.len())
+                    })
+                    .collect::<Vec<_>>(),
+            )?,
         };
 
-        let selection = checkboxes.interact()?;
-
         if selection.is_empty() {
-            println!("No decades selected.");
+            self.client.info("No decades selected.");
         } else {
             let default_name = selection
                 .iter()
-                .map(|i| &tracks_by_decades.get(*i).unwrap().0[2..])
+                .map(|i| &tracks_by_decades[*i].decade[2..])
                 .join("/");
             let tracks = selection
                 .into_iter()
-                .map(|i| &tracks_by_decades.get(i).unwrap().1)
-                .flatten()
+                .flat_map(|i| &tracks_by_decades[i].tracks)
                 .map(|track| &track.uri)
                 .collect::<Vec<_>>();
 
-            self.create_playlist(tracks, &default_name)?;
+            self.create_playlist(tracks, &default_name, &options.playlist)?;
         }
 
         Ok(())
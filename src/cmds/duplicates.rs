@@ -1,13 +1,22 @@
-use super::spotify_api::{
-    endpoints::{ALL_PLAYLISTS, GET_USER, SAVED_TRACKS, SAVED_TRACKS_REMOVAL},
-    models::{PlaylistTrack, SavedTrack, SimplifiedPlaylist, Track, User},
-};
 use super::CmdHandler;
+use crate::analysis::{self, Criteria, Keep, Strategy};
+use crate::api::{
+    endpoints::SAVED_TRACKS_REMOVAL,
+    models::{PlaylistItem, SimplifiedPlaylist, Track},
+};
+use crate::error::Error;
+use clap::Args;
 use console::style;
-use dialoguer::{Confirmation, Select};
+use itertools::Itertools;
+use reqwest::Method;
+use serde::Serialize;
 use serde_json::json;
-use std::collections::HashMap;
-use std::error::Error;
+use std::{fmt, iter};
+
+mod everywhere;
+
+/// Target looking for duplicates across the liked songs and all the playlists of the user
+const EVERYWHERE: &str = "all";
 
 /// Target for duplicate removal, either the saved tracks or a playlist
 enum Target {
@@ -15,16 +24,77 @@ enum Target {
     Playlist(SimplifiedPlaylist),
 }
 
-impl ToString for Target {
-    fn to_string(&self) -> String {
+impl Target {
+    /// How the target is given as an option, either "liked" or the name of the playlist
+    fn name(&self) -> &str {
+        match self {
+            Target::SavedTracks => "liked",
+            Target::Playlist(p) => &p.name,
+        }
+    }
+
+    fn playlist_id(&self) -> Option<&str> {
+        match self {
+            Target::SavedTracks => None,
+            Target::Playlist(p) => Some(&p.id),
+        }
+    }
+}
+
+/// Position of the target given as an option, among the liked songs and the playlists of the user
+fn find_target(targets: &[Target], name: &str) -> Result<usize, Error> {
+    targets
+        .iter()
+        .position(|target| match target {
+            Target::SavedTracks => name == "liked",
+            Target::Playlist(p) => p.name == name || p.id == name,
+        })
+        .ok_or_else(|| Error::Input(format!("No playlist named \"{}\" found.", name)))
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self {
-            Target::SavedTracks => String::from("Liked songs from your library"),
-            Target::Playlist(p) => format!("{} - {} tracks", p.name, p.tracks.total),
+            Target::SavedTracks => write!(f, "Liked songs from your library"),
+            Target::Playlist(p) => write!(f, "{} - {} tracks", p.name, p.tracks.total),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Args, Debug, Default)]
+pub struct DuplicatesOptions {
+    /// Where to remove duplicates from, either "liked", the name or ID of one of your playlists,
+    /// or "all" for the liked songs and all your playlists together
+    #[arg(long)]
+    pub target: Option<String>,
+
+    /// With `--target all`, remove the copies found in these places only, either "liked" or the name
+    /// or ID of one of your playlists. Songs found only in these places keep one copy.
+    #[arg(long, value_delimiter = ',')]
+    pub remove_from: Vec<String>,
+
+    /// How duplicates are recognized [default: name]
+    #[arg(long, value_enum)]
+    pub by: Option<Strategy>,
+
+    /// Minimum similarity of the normalized titles, from 0 to 1, when finding duplicates by fuzzy titles [default: 0.9]
+    #[arg(long)]
+    pub threshold: Option<f64>,
+
+    /// Which track of each group of duplicates is kept [default: album]
+    #[arg(long, value_enum)]
+    pub keep: Option<Keep>,
+
+    /// Remove the duplicates without asking for confirmation
+    #[arg(long, short)]
+    pub yes: bool,
+
+    /// Go through each group of duplicates to choose the track to keep, or to skip the group
+    #[arg(long, conflicts_with = "yes")]
+    pub review: bool,
+}
+
+#[derive(Serialize, Debug)]
 struct Duplicate<'a> {
     name: &'a str,
     album: &'a str,
@@ -32,93 +102,84 @@ struct Duplicate<'a> {
     id: &'a str,
     uri: &'a str,
     index: usize,
+    /// What it has in common with the kept track
+    key: String,
+    reason: String,
 }
 
 impl<'a> Duplicate<'a> {
-    fn from_indexed_track(indexed_track: (usize, &Track)) -> Duplicate {
+    fn new(track: &'a Track, index: usize, key: String, reason: String) -> Self {
         Duplicate {
-            name: &indexed_track.1.name,
-            album: &indexed_track.1.album.name,
-            artist: &indexed_track.1.artists[0].name,
-            id: &indexed_track.1.id,
-            uri: &indexed_track.1.uri,
-            index: indexed_track.0,
+            name: &track.name,
+            album: &track.album.name,
+            artist: &track.artists[0].name,
+            id: track.saved_id(),
+            uri: track.saved_uri(),
+            index,
+            key,
+            reason,
         }
     }
 }
 
-fn find_duplicates<'a>(tracks: &'a [Track]) -> Vec<Duplicate<'a>> {
-    let mut dup_map = HashMap::new();
-    let mut duplicates = Vec::new();
-    for indexed_track in tracks.iter().enumerate() {
-        dup_map
-            .entry(
-                indexed_track
-                    .1
-                    .artists
-                    .iter()
-                    .map(|artist| artist.name.to_owned())
-                    .collect::<Vec<_>>()
-                    .join("")
-                    .to_owned(),
-            ) // Entry with key based on track artist
-            .and_modify(|album_entry: &mut HashMap<&String, (usize, &Track)>| {
-                album_entry
-                    .entry(&indexed_track.1.name) // Entry with key based on track name
-                    .and_modify(|previous_track| {
-                        // Two track with the same name from the same artist found
-                        // Prefer a track from an album instead of a single
-                        match (
-                            &previous_track.1.album.album_type[..],
-                            &indexed_track.1.album.album_type[..],
-                        ) {
-                            ("single", "album") => {
-                                // Previous track is a single, new track is from an album, swap
-                                duplicates.push(Duplicate::from_indexed_track(*previous_track));
-                                *previous_track = indexed_track;
-                            }
-                            ("album", "single") => {
-                                // Previous track is from an album, mew track is a single, don't swap
-                                duplicates.push(Duplicate::from_indexed_track(indexed_track));
-                            }
-                            _ => {
-                                // If we can't select based on album_type prefer lower album id
-                                if indexed_track.1.album.id < previous_track.1.album.id {
-                                    // New track has lower album id, swap
-                                    duplicates.push(Duplicate::from_indexed_track(*previous_track));
-                                    *previous_track = indexed_track;
-                                } else {
-                                    // Previous track has lower album id, don't swap
-                                    duplicates.push(Duplicate::from_indexed_track(indexed_track));
-                                }
-                            }
-                        }
-                    })
-                    .or_insert(indexed_track); // First track with given name for that artist
-            })
-            .or_insert_with(|| {
-                // New entry for an artist, with a value containing a HashMap mapping a given track name to a unique track
-                let mut song_map = HashMap::new();
-                song_map.insert(&indexed_track.1.name, indexed_track);
-                song_map
-            });
+impl<'a> From<&analysis::Duplicate<'a>> for Duplicate<'a> {
+    fn from(duplicate: &analysis::Duplicate<'a>) -> Self {
+        Duplicate::new(
+            duplicate.track,
+            duplicate.index,
+            duplicate.key.to_owned(),
+            duplicate.reason.to_string(),
+        )
     }
-    duplicates
+}
+
+/// Description of a track for choosing between the tracks of a group,
+/// with its position in the target and when it was added
+fn describe(track: &Track, position: usize, added_at: Option<&str>) -> String {
+    format!(
+        "{} - {} ({}, {}, released {}, added {}, position {})",
+        track.artists[0].name,
+        track.name,
+        track.album.name,
+        track.album.album_type,
+        track.album.release_date,
+        added_at.map_or("on an unknown date", |date| date.get(0..10).unwrap_or(date)),
+        position + 1
+    )
+}
+
+#[derive(Serialize)]
+struct DuplicatesReport<'a> {
+    /// Either "liked" or the name of the playlist
+    target: &'a str,
+    playlist_id: Option<&'a str>,
+    by: Strategy,
+    /// Only with the fuzzy strategy
+    threshold: Option<f64>,
+    keep: Keep,
+    duplicates: &'a [Duplicate<'a>],
 }
 
 impl CmdHandler {
-    pub fn duplicates(&self) -> Result<(), Box<dyn Error>> {
-        println!("Loading your playlists...");
-        let playlists = self.paged_request::<SimplifiedPlaylist>(ALL_PLAYLISTS)?;
-        println!("Playlists loaded.");
-
-        let user_id = self
-            .client
-            .get(GET_USER)
-            .send()?
-            .error_for_status()?
-            .json::<User>()?
-            .id;
+    pub fn duplicates(&self, options: &DuplicatesOptions) -> Result<(), Error> {
+        if options.review && self.client.json_output() {
+            return Err(Error::Input(String::from(
+                "Duplicates can't be reviewed with JSON output.",
+            )));
+        }
+        let threshold = options.threshold.unwrap_or(analysis::DEFAULT_THRESHOLD);
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(Error::Input(format!(
+                "The threshold must be between 0 and 1, not {}.",
+                threshold
+            )));
+        }
+
+        self.client.info("Loading your playlists...");
+        let playlists = self.client.playlists()?;
+        self.client.info("Playlists loaded.");
+
+        let user_id = self.client.current_user()?.id;
 
         let choices = {
             let mut choices = vec![Target::SavedTracks];
@@ -131,87 +192,299 @@ impl CmdHandler {
             choices
         };
 
-        let select = {
-            let mut select = Select::new();
-            select.with_prompt(&style("Remove duplicates from").cyan().to_string());
-            select.items(&choices);
-            select.default(0);
-            select
+        // None when looking everywhere at once
+        let target = match &options.target {
+            Some(name) if name == EVERYWHERE => None,
+            Some(name) => Some(&choices[find_target(&choices, name)?]),
+            None => {
+                let mut items = choices.iter().map(Target::to_string).collect::<Vec<_>>();
+                items.push(String::from("Liked songs and all your playlists together"));
+                choices.get(
+                    self.client
+                        .ui()
+                        .select("Remove duplicates from", &items, 0)?,
+                )
+            }
         };
+        if target.is_some() && !options.remove_from.is_empty() {
+            return Err(Error::Input(format!(
+                "Copies can only be removed from chosen places with --target {}.",
+                EVERYWHERE
+            )));
+        }
 
-        let target = choices.get(select.interact().unwrap_or(0)).unwrap();
-
-        println!("Looking for duplicates...");
-        let tracks = match &target {
-            Target::SavedTracks => {
-                let tracks = self.paged_request::<SavedTrack>(SAVED_TRACKS)?;
-                tracks.into_iter().map(|t| t.track).collect::<Vec<_>>()
+        let strategy = match options.by {
+            Some(strategy) => strategy,
+            // Only asked along with the target, so that giving the target is enough to run unattended
+            None if options.target.is_none() => {
+                let strategies = [Strategy::Name, Strategy::Isrc, Strategy::Fuzzy];
+                strategies[self.client.ui().select(
+                    "Find duplicates by",
+                    &[
+                        String::from("Same name and artists"),
+                        String::from(
+                            "Same recording (ISRC), also across albums, singles and compilations",
+                        ),
+                        String::from(
+                            "Similar titles, ignoring feature credits, remaster and edition suffixes",
+                        ),
+                    ],
+                    0,
+                )?]
             }
-            Target::Playlist(p) => {
-                let tracks = self.paged_request::<PlaylistTrack>(&p.tracks.href)?;
-                tracks.into_iter().map(|t| t.track).collect::<Vec<_>>()
+            None => Strategy::default(),
+        };
+
+        let keep = match options.keep {
+            Some(keep) => keep,
+            None if options.target.is_none() => {
+                let policies = [
+                    (Keep::Album, "The album version"),
+                    (Keep::Single, "The single version"),
+                    (Keep::Compilation, "The compilation version"),
+                    (Keep::Earliest, "The earliest release"),
+                    (Keep::Latest, "The latest release"),
+                    (Keep::Popular, "The most popular one"),
+                    (Keep::Explicit, "The explicit one"),
+                    (Keep::Clean, "The clean one"),
+                    (Keep::FirstAdded, "The one added first"),
+                    (Keep::First, "The first one in the list"),
+                    (Keep::Last, "The last one in the list"),
+                ];
+                policies[self.client.ui().select(
+                    "Keep from each group of duplicates",
+                    &policies
+                        .iter()
+                        .map(|(_, label)| String::from(*label))
+                        .collect::<Vec<_>>(),
+                    0,
+                )?]
+                .0
             }
+            None => Keep::default(),
         };
 
-        let duplicates = find_duplicates(&tracks);
+        let criteria = Criteria {
+            strategy,
+            threshold,
+            keep,
+        };
+        let target = match target {
+            Some(target) => target,
+            None => return self.duplicates_everywhere(options, &criteria, &choices),
+        };
+
+        self.client.info("Looking for duplicates...");
+        let (listed, tracks) = self.load_tracks(target)?;
+        let added_at = listed
+            .iter()
+            .map(|(_, added_at)| added_at.as_deref())
+            .collect::<Vec<_>>();
+        let groups = analysis::group_duplicates(&tracks, &added_at, &criteria);
+        let duplicates = groups
+            .iter()
+            .flat_map(|group| &group.duplicates)
+            .map(|duplicate| Duplicate {
+                index: listed[duplicate.index].0,
+                ..Duplicate::from(duplicate)
+            })
+            .sorted_by_key(|duplicate| duplicate.index)
+            .collect::<Vec<_>>();
+
+        if self.client.json_output() {
+            self.print_json(&DuplicatesReport {
+                target: target.name(),
+                playlist_id: target.playlist_id(),
+                by: strategy,
+                threshold: Some(threshold).filter(|_| strategy == Strategy::Fuzzy),
+                keep,
+                duplicates: &duplicates,
+            })?;
+        } else if !duplicates.is_empty() {
+            self.client
+                .info(style(format!("Found {} duplicates:", duplicates.len())).cyan());
+            for dup in &duplicates {
+                self.client.info(format!(
+                    "{} - {} ({}) {}",
+                    dup.artist,
+                    dup.name,
+                    dup.album,
+                    style(format!("[{}: {}]", dup.reason, dup.key)).dim()
+                ));
+            }
+        }
 
         if duplicates.is_empty() {
-            println!("No duplicates found.");
+            self.client.info("No duplicates found.");
         } else {
-            println!(
-                "{}",
-                style(format!("Found {} duplicates:", duplicates.len())).cyan()
+            let review = options.review
+                || (options.target.is_none()
+                    && !self.client.json_output()
+                    && self
+                        .client
+                        .ui()
+                        .confirm("Do you want to review each group?", false)?);
+            if review {
+                let approved = self.review(&groups, &tracks, &listed)?;
+                if approved.is_empty() {
+                    self.client.info("No duplicates removed.");
+                } else {
+                    self.remove_duplicates(target, &approved)?;
+                    self.client.info("Duplicates removed successfully.");
+                }
+            // With JSON output the duplicates are only removed when explicitly requested, without prompting
+            } else if options.yes
+                || (!self.client.json_output()
+                    && self
+                        .client
+                        .ui()
+                        .confirm("Do you want to delete them?", false)?)
+            {
+                self.remove_duplicates(target, &duplicates)?;
+                self.client.info("Duplicates removed successfully.");
+            } else {
+                self.client.info("No duplicates removed.");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ask which track of each group to keep.
+    /// Returns the other tracks of the groups that weren't skipped, sorted by their position.
+    fn review<'a>(
+        &self,
+        groups: &[analysis::Group<'a>],
+        tracks: &'a [Track],
+        listed: &[(usize, Option<String>)],
+    ) -> Result<Vec<Duplicate<'a>>, Error> {
+        let mut approved = Vec::new();
+        for (number, group) in groups.iter().enumerate() {
+            // Duplicates along with the kept track, which has no reason
+            let candidates = group
+                .duplicates
+                .iter()
+                .map(|duplicate| (duplicate.index, Some(duplicate)))
+                .chain(iter::once((group.kept, None)))
+                .sorted_by_key(|(index, _)| *index)
+                .collect::<Vec<_>>();
+            let mut items = candidates
+                .iter()
+                .map(|(index, _)| {
+                    let (position, added_at) = &listed[*index];
+                    describe(&tracks[*index], *position, added_at.as_deref())
+                })
+                .collect::<Vec<_>>();
+            items.push(String::from("Skip this group"));
+
+            self.client.info(
+                style(format!(
+                    "Group {} of {}: {}",
+                    number + 1,
+                    groups.len(),
+                    group.key
+                ))
+                .cyan(),
             );
-            for dup in &duplicates {
-                println!("{} - {} ({})", dup.artist, dup.name, dup.album);
+            let kept = candidates
+                .iter()
+                .position(|(_, duplicate)| duplicate.is_none())
+                .unwrap();
+            let choice = self.client.ui().select("Track to keep", &items, kept)?;
+            if choice == candidates.len() {
+                continue;
+            }
+            for (_, (index, duplicate)) in candidates
+                .iter()
+                .enumerate()
+                .filter(|(candidate, _)| *candidate != choice)
+            {
+                let (key, reason) = match duplicate {
+                    Some(duplicate) => (duplicate.key.to_owned(), duplicate.reason.to_string()),
+                    None => (group.key.to_owned(), String::from("not chosen in review")),
+                };
+                approved.push(Duplicate::new(
+                    &tracks[*index],
+                    listed[*index].0,
+                    key,
+                    reason,
+                ));
             }
+        }
+        approved.sort_by_key(|duplicate| duplicate.index);
+        Ok(approved)
+    }
 
-            let confirm = {
-                let mut confirm = Confirmation::new();
-                confirm.with_text(&style("Do you want to delete them?").cyan().to_string());
-                confirm.default(false);
-                confirm
-            };
-
-            if confirm.interact()? {
-                match &target {
-                    Target::SavedTracks => {
-                        let chunks = duplicates.chunks(50);
-                        for chunk in chunks {
-                            let data = chunk.iter().map(|c| &c.id).collect::<Vec<_>>();
-                            self.client
-                                .delete(SAVED_TRACKS_REMOVAL)
-                                .json(&data)
-                                .send()?
-                                .error_for_status()?;
-                        }
-                        println!("Duplicates removed successfully.");
-                    }
-                    Target::Playlist(p) => {
-                        let chunks = duplicates.chunks(100);
-                        for chunk in chunks {
-                            self.client
-                                .delete(&p.tracks.href)
-                                .json(&json!({
-                                    "tracks": chunk.iter().map(|c| {
-                                            json!({
-                                                "uri": &c.uri,
-                                                "positions": [&c.index]
-                                            })
-                                        }).collect::<Vec<_>>(),
-                                    "snapshot_id": &p.snapshot_id
-                                }))
-                                .send()?
-                                .error_for_status()?;
+    /// Tracks of the target with their position and when they were added,
+    /// the other items of a playlist aren't compared
+    #[allow(clippy::type_complexity)]
+    fn load_tracks(
+        &self,
+        target: &Target,
+    ) -> Result<(Vec<(usize, Option<String>)>, Vec<Track>), Error> {
+        Ok(match target {
+            Target::SavedTracks => {
+                let tracks = self.client.saved_tracks()?;
+                tracks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(position, t)| ((position, Some(t.added_at)), t.track))
+                    .unzip()
+            }
+            Target::Playlist(p) => {
+                let items = self.client.playlist_tracks(p)?;
+                let count = items.len();
+                let tracks: (Vec<_>, Vec<_>) = items
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(position, item)| match item.track {
+                        Some(PlaylistItem::Track(track)) => {
+                            Some(((position, item.added_at), track))
                         }
-                        println!("Duplicates removed successfully.");
-                    }
+                        _ => None,
+                    })
+                    .unzip();
+                if tracks.1.len() < count {
+                    self.client.info(format!(
+                        "Skipped {} local files, episodes or unavailable items from {}.",
+                        count - tracks.1.len(),
+                        p.name
+                    ));
                 }
-            } else {
-                println!("No duplicates removed.");
+                tracks
             }
-        }
+        })
+    }
 
+    fn remove_duplicates(&self, target: &Target, duplicates: &[Duplicate]) -> Result<(), Error> {
+        match target {
+            Target::SavedTracks => {
+                let chunks = duplicates.chunks(50);
+                for chunk in chunks {
+                    let data = chunk.iter().map(|c| &c.id).collect::<Vec<_>>();
+                    self.client
+                        .modify(Method::DELETE, SAVED_TRACKS_REMOVAL, &json!(data))?;
+                }
+            }
+            Target::Playlist(p) => {
+                let chunks = duplicates.chunks(100);
+                for chunk in chunks {
+                    self.client.modify(
+                        Method::DELETE,
+                        &p.tracks.href,
+                        &json!({
+                            "tracks": chunk.iter().map(|c| {
+                                    json!({
+                                        "uri": &c.uri,
+                                        "positions": [&c.index]
+                                    })
+                                }).collect::<Vec<_>>(),
+                            "snapshot_id": &p.snapshot_id
+                        }),
+                    )?;
+                }
+            }
+        }
         Ok(())
     }
 }
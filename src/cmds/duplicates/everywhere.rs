@@ -0,0 +1,291 @@
+//! Duplicates across the liked songs and all the playlists of the user, looked for together
+use super::{find_target, CmdHandler, Duplicate, DuplicatesOptions, Target, EVERYWHERE};
+use crate::analysis::{self, Criteria, Keep, Strategy};
+use crate::api::models::Track;
+use crate::error::Error;
+use console::style;
+use itertools::Itertools;
+use serde::Serialize;
+use std::{fmt, iter};
+
+/// How the copies of a song are spread over the liked songs and the playlists
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Overlap {
+    SeveralPlaces,
+    /// Different tracks of the song are liked, e.g. from an album and from a single
+    LikedReleases,
+    SeveralPlaylists,
+    SamePlaylist,
+}
+
+impl fmt::Display for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Overlap::SeveralPlaces => write!(f, "in several places"),
+            Overlap::LikedReleases => write!(f, "liked under different releases"),
+            Overlap::SeveralPlaylists => write!(f, "in several playlists"),
+            Overlap::SamePlaylist => write!(f, "repeated in a playlist"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Occurrence<'a> {
+    name: &'a str,
+    album: &'a str,
+    artist: &'a str,
+    id: &'a str,
+    uri: &'a str,
+    /// Either "liked" or the name of the playlist
+    place: &'a str,
+    playlist_id: Option<&'a str>,
+    /// Position in the place
+    index: usize,
+    /// Whether it's the copy kept according to the criteria
+    kept: bool,
+    /// Why it's the same song as the kept copy
+    reason: Option<String>,
+    /// Whether it's removed from the chosen places
+    removed: bool,
+    #[serde(skip)]
+    target: usize,
+    #[serde(skip)]
+    track: &'a Track,
+}
+
+/// Song found more than once, with all its copies sorted by place
+#[derive(Serialize, Debug)]
+struct Song<'a> {
+    key: String,
+    overlaps: Vec<Overlap>,
+    copies: Vec<Occurrence<'a>>,
+}
+
+impl Song<'_> {
+    /// Mark the copies found in the chosen places as removed.
+    /// When all of them are in these places, the kept copy is left.
+    fn remove_from(&mut self, targets: &[usize]) {
+        let all = self
+            .copies
+            .iter()
+            .all(|copy| targets.contains(&copy.target));
+        for copy in &mut self.copies {
+            copy.removed = targets.contains(&copy.target) && !(all && copy.kept);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EverywhereReport<'a> {
+    target: &'a str,
+    by: Strategy,
+    /// Only with the fuzzy strategy
+    threshold: Option<f64>,
+    keep: Keep,
+    remove_from: Vec<&'a str>,
+    songs: &'a [Song<'a>],
+}
+
+impl CmdHandler {
+    /// Look for songs found more than once across the targets, and remove copies from the chosen ones
+    pub(super) fn duplicates_everywhere(
+        &self,
+        options: &DuplicatesOptions,
+        criteria: &Criteria,
+        targets: &[Target],
+    ) -> Result<(), Error> {
+        if options.review {
+            return Err(Error::Input(format!(
+                "Duplicates can't be reviewed with --target {}.",
+                EVERYWHERE
+            )));
+        }
+        let mut remove_from = options
+            .remove_from
+            .iter()
+            .map(|name| find_target(targets, name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.client.info("Looking for duplicates...");
+        // Target, position and added date of every track, the tracks of all the targets one after the other
+        let mut listed = Vec::new();
+        let mut tracks = Vec::new();
+        for (index, target) in targets.iter().enumerate() {
+            let (target_listed, target_tracks) = self.load_tracks(target)?;
+            listed.extend(
+                target_listed
+                    .into_iter()
+                    .map(|(position, added_at)| (index, position, added_at)),
+            );
+            tracks.extend(target_tracks);
+        }
+        let added_at = listed
+            .iter()
+            .map(|(_, _, added_at)| added_at.as_deref())
+            .collect::<Vec<_>>();
+
+        let mut songs = analysis::group_duplicates(&tracks, &added_at, criteria)
+            .into_iter()
+            .map(|group| {
+                let copies = group
+                    .duplicates
+                    .iter()
+                    .map(|duplicate| (duplicate.index, Some(duplicate.reason.to_string())))
+                    .chain(iter::once((group.kept, None)))
+                    .sorted_by_key(|(index, _)| *index)
+                    .map(|(index, reason)| {
+                        let (target, position, _) = listed[index];
+                        let track = &tracks[index];
+                        Occurrence {
+                            name: &track.name,
+                            album: &track.album.name,
+                            artist: &track.artists[0].name,
+                            id: track.saved_id(),
+                            uri: track.saved_uri(),
+                            place: targets[target].name(),
+                            playlist_id: targets[target].playlist_id(),
+                            index: position,
+                            kept: reason.is_none(),
+                            reason,
+                            removed: false,
+                            target,
+                            track,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Song {
+                    key: group.key,
+                    overlaps: overlaps(&copies, targets),
+                    copies,
+                }
+            })
+            .collect::<Vec<_>>();
+        for song in &mut songs {
+            song.remove_from(&remove_from);
+        }
+
+        if self.client.json_output() {
+            self.print_json(&EverywhereReport {
+                target: EVERYWHERE,
+                by: criteria.strategy,
+                threshold: Some(criteria.threshold)
+                    .filter(|_| criteria.strategy == Strategy::Fuzzy),
+                keep: criteria.keep,
+                remove_from: remove_from
+                    .iter()
+                    .map(|&target| targets[target].name())
+                    .collect(),
+                songs: &songs,
+            })?;
+        } else if !songs.is_empty() {
+            self.client.info(
+                style(format!(
+                    "Found {} songs in several places or repeated:",
+                    songs.len()
+                ))
+                .cyan(),
+            );
+            for song in &songs {
+                self.client.info(format!(
+                    "{} ({})",
+                    song.key,
+                    song.overlaps.iter().join(", ")
+                ));
+                for copy in &song.copies {
+                    self.client.info(format!(
+                        "  {} ({}) - {}, position {} {}",
+                        copy.name,
+                        copy.album,
+                        copy.place,
+                        copy.index + 1,
+                        style(format!("[{}]", copy.reason.as_deref().unwrap_or("kept"))).dim()
+                    ));
+                }
+            }
+        }
+
+        if songs.is_empty() {
+            self.client.info("No duplicates found.");
+            return Ok(());
+        }
+        if remove_from.is_empty() && !self.client.json_output() {
+            remove_from = self.client.ui().multi_select(
+                "Remove copies from",
+                &targets.iter().map(Target::to_string).collect::<Vec<_>>(),
+            )?;
+            for song in &mut songs {
+                song.remove_from(&remove_from);
+            }
+        }
+
+        let removed = songs
+            .iter()
+            .flat_map(|song| song.copies.iter().map(move |copy| (song, copy)))
+            .filter(|(_, copy)| copy.removed)
+            .collect::<Vec<_>>();
+        // With JSON output the copies are only removed when explicitly requested, without prompting
+        if !removed.is_empty()
+            && (options.yes
+                || (!self.client.json_output()
+                    && self.client.ui().confirm(
+                        &format!("Do you want to delete {} copies?", removed.len()),
+                        false,
+                    )?))
+        {
+            for (target, copies) in &removed
+                .into_iter()
+                .sorted_by_key(|(_, copy)| copy.target)
+                .group_by(|(_, copy)| copy.target)
+            {
+                let duplicates = copies
+                    .map(|(song, copy)| {
+                        Duplicate::new(
+                            copy.track,
+                            copy.index,
+                            song.key.to_owned(),
+                            copy.reason.clone().unwrap_or_default(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                self.remove_duplicates(&targets[target], &duplicates)?;
+            }
+            self.client.info("Duplicates removed successfully.");
+        } else {
+            self.client.info("No duplicates removed.");
+        }
+
+        Ok(())
+    }
+}
+
+/// How the copies of a song are spread over the targets
+fn overlaps(copies: &[Occurrence], targets: &[Target]) -> Vec<Overlap> {
+    let places = copies.iter().map(|copy| copy.target).unique().count();
+    let playlists = copies
+        .iter()
+        .filter(|copy| copy.playlist_id.is_some())
+        .map(|copy| copy.target)
+        .collect::<Vec<_>>();
+    let liked_tracks = copies
+        .iter()
+        .filter(|copy| matches!(targets[copy.target], Target::SavedTracks))
+        .map(|copy| copy.id)
+        .unique()
+        .count();
+
+    let mut overlaps = Vec::new();
+    if places > 1 {
+        overlaps.push(Overlap::SeveralPlaces);
+    }
+    if liked_tracks > 1 {
+        overlaps.push(Overlap::LikedReleases);
+    }
+    if playlists.iter().unique().count() > 1 {
+        overlaps.push(Overlap::SeveralPlaylists);
+    }
+    if playlists.iter().unique().count() < playlists.len() {
+        overlaps.push(Overlap::SamePlaylist);
+    }
+    overlaps
+}
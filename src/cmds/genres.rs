@@ -1,141 +1,99 @@
-use super::spotify_api::{
-    endpoints::{ARTISTS_INFO, SAVED_TRACKS},
-    models::{ArtistsResponse, SavedTrack, Track},
-};
+use super::util::PlaylistOptions;
 use super::CmdHandler;
-use console::style;
-use dialoguer::Checkboxes;
-use indicatif::{ProgressBar, ProgressStyle};
+use crate::analysis;
+use crate::error::Error;
+use clap::Args;
 use itertools::Itertools;
+use serde::Serialize;
 use std::cmp::min;
-use std::collections::HashMap;
-use std::convert::TryInto;
-use std::error::Error;
 
-impl CmdHandler {
-    pub fn genres(&self) -> Result<(), Box<dyn Error>> {
-        println!("Loading your library information...");
-        let saved_tracks = self.paged_request::<SavedTrack>(SAVED_TRACKS)?;
-        println!("Library loaded.");
+#[derive(Args, Debug, Default)]
+pub struct GenresOptions {
+    /// Genres (or artists) to create the playlist from, e.g. "indie rock,dream pop"
+    #[arg(long, value_delimiter = ',')]
+    pub select: Option<Vec<String>>,
 
-        println!("Getting genre information...");
-        let mut artist_map = HashMap::new();
-        for saved_track in &saved_tracks {
-            let track = &saved_track.track;
-            artist_map
-                .entry(&track.artists[0].id)
-                .and_modify(|artist_tracks: &mut Vec<&Track>| {
-                    artist_tracks.push(track);
-                })
-                .or_insert_with(|| vec![track]);
-        }
+    #[command(flatten)]
+    pub playlist: PlaylistOptions,
+}
 
-        let artists = artist_map.keys().collect::<Vec<_>>();
-        let chunks = artists.chunks(50);
-        let mut genre_map: HashMap<String, Vec<(String, String)>> = HashMap::new();
+#[derive(Serialize)]
+struct Genre<'a> {
+    genre: &'a str,
+    artists: &'a [String],
+    tracks: Vec<&'a str>,
+}
 
-        let progress = ProgressBar::new(artists.len().try_into().unwrap()).with_style(
-            ProgressStyle::default_bar()
-                .template("[{wide_bar}] {pos}/{len}")
-                .progress_chars("=> "),
-        );
-        for chunk in chunks {
-            let data = self
-                .client
-                .get(&format!("{}?ids={}", ARTISTS_INFO, chunk.iter().join(",")))
-                .send()?
-                .error_for_status()?
-                .json::<ArtistsResponse>()?;
-            progress.inc(50);
-            for artist in &data.artists {
-                for genre in &artist.genres {
-                    genre_map
-                        .entry(String::from(genre))
-                        .and_modify(|artists: &mut Vec<(String, String)>| {
-                            artists.push((String::from(&artist.id), String::from(&artist.name)));
-                        })
-                        .or_insert_with(|| {
-                            vec![(String::from(&artist.id), String::from(&artist.name))]
-                        });
-                }
+impl CmdHandler {
+    pub fn genres(&self, options: &GenresOptions) -> Result<(), Error> {
+        self.client.info("Loading your library information...");
+        let saved_tracks = self.client.saved_tracks()?;
+        self.client.info("Library loaded.");
 
-                genre_map.insert(
-                    String::from(&artist.name),
-                    vec![(String::from(&artist.id), String::from(&artist.name))],
-                );
+        self.client.info("Getting genre information...");
+        let tracks = saved_tracks.iter().map(|saved| &saved.track);
+        let artists = self
+            .client
+            .artists(&analysis::main_artists(tracks.clone()))?;
+        let genres = analysis::group_by_genre(tracks, &artists);
+        self.client.info("Genre information loaded.");
+
+        if self.client.json_output() {
+            self.print_json(
+                &genres
+                    .iter()
+                    .map(|genre| Genre {
+                        genre: &genre.genre,
+                        artists: &genre.artists,
+                        tracks: genre.tracks.iter().map(|track| &track.uri[..]).collect(),
+                    })
+                    .collect::<Vec<_>>(),
+            )?;
+            // With JSON output a playlist is only created when the genres are given explicitly
+            if options.select.is_none() {
+                return Ok(());
             }
         }
-        progress.finish_and_clear();
-        println!("Genre information loaded.");
 
-        let genres = genre_map
-            .into_iter()
-            .map(|(genre, artists)| {
-                (
-                    genre,
-                    (
-                        artists
-                            .iter()
-                            .map(|artist| artist_map.get(&artist.0).unwrap())
-                            .flatten()
-                            .collect::<Vec<_>>(),
-                        artists
-                            .into_iter()
-                            .map(|artist| artist.1)
-                            .collect::<Vec<_>>(),
-                    ),
-                )
-            })
-            .sorted_by(
-                |(_id1, (tracks1, _artists1)), (_id2, (tracks2, _artists2))| {
-                    tracks2.len().cmp(&tracks1.len())
-                },
-            )
-            .collect::<Vec<_>>();
-        let checkboxes = {
-            let mut checkboxes = Checkboxes::new();
-            checkboxes.with_prompt(
-                &style("Select genres to create your playlist from")
-                    .cyan()
-                    .to_string(),
-            );
-            checkboxes.items(
+        let selection = match &options.select {
+            Some(selected) => selected
+                .iter()
+                .map(|name| {
+                    genres
+                        .iter()
+                        .position(|genre| genre.genre.eq_ignore_ascii_case(name))
+                        .ok_or_else(|| {
+                            Error::Input(format!("No songs from the {} genre found.", name))
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => self.client.ui().multi_select(
+                "Select genres to create your playlist from",
                 &genres
                     .iter()
-                    .map(|(genre, (tracks, artists))| {
+                    .map(|genre| {
                         format!(
                             "{} - {} songs ({})",
-                            &genre,
-                            tracks.len(),
-                            &artists[..min(artists.len(), 5)].join(", ")
+                            &genre.genre,
+                            genre.tracks.len(),
+                            &genre.artists[..min(genre.artists.len(), 5)].join(", ")
                         )
                     })
-                    .collect::<Vec<String>>()
-                    .iter()
-                    .map(|s| s.as_ref())
-                    .collect::<Vec<&str>>()[..],
-            );
-            checkboxes.paged(true);
-            checkboxes
+                    .collect::<Vec<_>>(),
+            )?,
         };
-
-        let selection = checkboxes.interact()?;
         if selection.is_empty() {
-            println!("No genres selected.");
+            self.client.info("No genres selected.");
         } else {
-            let default_name = selection
-                .iter()
-                .map(|i| &genres.get(*i).unwrap().0)
-                .join("/");
+            let default_name = selection.iter().map(|i| &genres[*i].genre).join("/");
             let tracks = selection
                 .into_iter()
-                .map(|i| &(genres.get(i).unwrap().1).0)
-                .flatten()
+                .flat_map(|i| &genres[i].tracks)
                 .map(|track| &track.uri)
                 .unique()
                 .collect::<Vec<_>>();
 
-            self.create_playlist(tracks, &default_name)?;
+            self.create_playlist(tracks, &default_name, &options.playlist)?;
         }
 
         Ok(())
@@ -0,0 +1,87 @@
+use super::CmdHandler;
+use crate::api::models::PlaylistTrack;
+use crate::database::{self, Database, Library};
+use crate::error::Error;
+use clap::Args;
+use console::style;
+use itertools::Itertools;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Location of the SQLite mirror
+#[derive(Args, Debug, Default)]
+pub struct DatabaseOptions {
+    /// Path of the database [default: <cache dir>/spotify-tools/library/<profile>.sqlite]
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct MirrorInfo<'a> {
+    database: &'a str,
+    songs: usize,
+    playlists: usize,
+    artists: usize,
+}
+
+impl CmdHandler {
+    pub fn database_path(&self, options: &DatabaseOptions) -> Result<PathBuf, Error> {
+        match &options.database {
+            Some(path) => Ok(path.to_owned()),
+            None => database::database_path(self.client.profile()),
+        }
+    }
+
+    pub fn mirror(&self, options: &DatabaseOptions) -> Result<(), Error> {
+        self.client.info("Loading your library information...");
+        let saved_tracks = self.client.saved_tracks()?;
+        let mut playlists = Vec::new();
+        for playlist in self.client.playlists()? {
+            let tracks = self.client.playlist_tracks(&playlist)?;
+            playlists.push((playlist, tracks));
+        }
+        self.client.info("Library loaded.");
+
+        self.client.info("Getting artist information...");
+        let artist_ids = saved_tracks
+            .iter()
+            .map(|saved| &saved.track)
+            .chain(
+                playlists
+                    .iter()
+                    .flat_map(|(_, tracks)| tracks.iter().filter_map(PlaylistTrack::track)),
+            )
+            .flat_map(|track| track.artists.iter().map(|artist| &artist.id[..]))
+            .unique()
+            .collect::<Vec<_>>();
+        let artists = self.client.artists(&artist_ids)?;
+        self.client.info("Artist information loaded.");
+
+        let path = self.database_path(options)?;
+        Database::create(&path)?.mirror(&Library {
+            saved_tracks: &saved_tracks,
+            playlists: &playlists,
+            artists: &artists,
+        })?;
+
+        if self.client.json_output() {
+            return self.print_json(&MirrorInfo {
+                database: &path.to_string_lossy(),
+                songs: saved_tracks.len(),
+                playlists: playlists.len(),
+                artists: artists.len(),
+            });
+        }
+        self.client.info(
+            style(format!(
+                "Mirrored {} liked songs, {} playlists and {} artists to {}.",
+                saved_tracks.len(),
+                playlists.len(),
+                artists.len(),
+                path.display()
+            ))
+            .cyan(),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use super::mirror::DatabaseOptions;
+use super::util::PlaylistOptions;
+use super::CmdHandler;
+use crate::database::{Database, QueryResult};
+use crate::error::Error;
+use clap::Args;
+use serde_json::{Map, Value};
+
+#[derive(Args, Debug, Default)]
+pub struct QueryOptions {
+    /// SQL query to run against the mirrored library, see `mirror` for the schema
+    pub sql: Option<String>,
+
+    #[command(flatten)]
+    pub database: DatabaseOptions,
+
+    /// Tracks from the `uri` column of the results can be turned into a playlist
+    #[command(flatten)]
+    pub playlist: PlaylistOptions,
+}
+
+/// Text form of a value, strings without their quotes
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.to_owned(),
+        value => value.to_string(),
+    }
+}
+
+impl CmdHandler {
+    pub fn query(&self, options: &QueryOptions) -> Result<(), Error> {
+        let database = Database::open(&self.database_path(&options.database)?)?;
+        let sql = match &options.sql {
+            Some(sql) => sql.to_owned(),
+            None => self.client.ui().input("Enter an SQL query", None)?,
+        };
+        let result = database.query(&sql)?;
+
+        if self.client.json_output() {
+            self.print_json(
+                &result
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        result
+                            .columns
+                            .iter()
+                            .cloned()
+                            .zip(row.iter().cloned())
+                            .collect::<Map<_, _>>()
+                    })
+                    .collect::<Vec<_>>(),
+            )?;
+        } else {
+            self.print_table(&result);
+        }
+
+        let uris = match result.columns.iter().position(|column| column == "uri") {
+            Some(index) => result
+                .rows
+                .iter()
+                .filter_map(|row| row[index].as_str())
+                .filter(|uri| uri.starts_with("spotify:track:"))
+                .map(String::from)
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+        // With JSON output a playlist is only created when its name is given explicitly
+        if uris.is_empty()
+            || (self.client.json_output() && options.playlist.playlist_name.is_none())
+        {
+            return Ok(());
+        }
+        if options.playlist.playlist_name.is_some()
+            || self.client.ui().confirm(
+                &format!(
+                    "Do you want to create a playlist from the {} tracks?",
+                    uris.len()
+                ),
+                false,
+            )?
+        {
+            self.create_playlist(uris.iter().collect(), "Query results", &options.playlist)?;
+        }
+        Ok(())
+    }
+
+    /// Print the results as aligned columns
+    fn print_table(&self, result: &QueryResult) {
+        let rows = result
+            .rows
+            .iter()
+            .map(|row| row.iter().map(cell).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let widths = result
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| {
+                rows.iter()
+                    .map(|row| row[index].chars().count())
+                    .chain(Some(column.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        let line = |cells: &[String]| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join(" | ")
+                .trim_end()
+                .to_owned()
+        };
+
+        self.client.info(line(&result.columns));
+        self.client.info(
+            widths
+                .iter()
+                .map(|width| "-".repeat(*width))
+                .collect::<Vec<_>>()
+                .join("-+-"),
+        );
+        for row in &rows {
+            self.client.info(line(row));
+        }
+        self.client.info(format!("({} rows)", rows.len()));
+    }
+}
@@ -0,0 +1,66 @@
+use super::CmdHandler;
+use crate::analysis;
+use crate::error::Error;
+use console::style;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct CacheInfo {
+    songs: usize,
+    artists: usize,
+    playlists: usize,
+    playlist_tracks: usize,
+}
+
+impl CmdHandler {
+    pub fn refresh_cache(&self) -> Result<(), Error> {
+        if !self.client.config().cache {
+            return Err(Error::Input(String::from(
+                "The cache is disabled, there is nothing to refresh.",
+            )));
+        }
+        if self.client.config().offline {
+            return Err(Error::Offline(String::from(
+                "The cache can't be refreshed offline.",
+            )));
+        }
+
+        self.client.clear_cache()?;
+        self.client.info("Downloading your library...");
+        let saved_tracks = self.client.saved_tracks()?;
+        let songs = saved_tracks.len();
+        // Needed for the genres
+        let artists = self
+            .client
+            .artists(&analysis::main_artists(
+                saved_tracks.iter().map(|saved| &saved.track),
+            ))?
+            .len();
+        let playlists = self.client.playlists()?;
+        let mut playlist_tracks = 0;
+        for playlist in &playlists {
+            playlist_tracks += self.client.playlist_tracks(playlist)?.len();
+        }
+
+        if self.client.json_output() {
+            return self.print_json(&CacheInfo {
+                songs,
+                artists,
+                playlists: playlists.len(),
+                playlist_tracks,
+            });
+        }
+
+        self.client.info(
+            style(format!(
+                "Cached {} liked songs by {} artists and {} playlists containing {} tracks.",
+                songs,
+                artists,
+                playlists.len(),
+                playlist_tracks
+            ))
+            .cyan(),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use super::CmdHandler;
+use crate::api::{endpoints::PLAYLIST_CREATION, models::SimplifiedPlaylist};
+use crate::client;
+use crate::error::Error;
+use crate::queue::Queue;
+use console::style;
+
+impl CmdHandler {
+    pub fn sync(&self) -> Result<(), Error> {
+        if self.client.config().offline {
+            return Err(Error::Offline(String::from(
+                "Queued changes can't be sent offline.",
+            )));
+        }
+
+        let profile = self.client.profile();
+        let mut queue = Queue::load(profile)?;
+        if queue.changes.is_empty() {
+            self.client.info("No changes are queued.");
+            return Ok(());
+        }
+        self.client.info(format!(
+            "Sending {} changes made offline...",
+            queue.changes.len()
+        ));
+
+        if self.client.config().dry_run {
+            for change in &queue.changes {
+                self.client
+                    .modify(change.method()?, &change.url, &change.body)?;
+            }
+            return Ok(());
+        }
+
+        let mut sent = 0;
+        while !queue.changes.is_empty() {
+            let change = queue.changes.remove(0);
+            let response = self
+                .client
+                .send(change.method()?, &change.url, Some(&change.body))?;
+            if change.creates_playlist() {
+                let playlist = client::decode::<SimplifiedPlaylist>(PLAYLIST_CREATION, response)?;
+                queue.resolve_created(&playlist.tracks.href);
+            }
+            // Saved after every change, so that a failure leaves only the unsent ones queued
+            queue.save(profile)?;
+            sent += 1;
+        }
+
+        self.client
+            .info(style(format!("Sent {} changes.", sent)).cyan());
+        Ok(())
+    }
+}
@@ -1,66 +1,67 @@
-use super::spotify_api::{endpoints::SAVED_TRACKS, models::SavedTrack};
 use super::CmdHandler;
+use crate::analysis::{self, Count};
+use crate::error::Error;
 use console::style;
-use itertools::Itertools;
+use serde::Serialize;
 use std::cmp::min;
-use std::collections::HashMap;
-use std::error::Error;
 
+#[derive(Serialize)]
 struct NamedCounter<'a> {
+    id: &'a str,
     name: &'a str,
-    counter: u32,
+    songs: u32,
 }
 
-impl CmdHandler {
-    pub fn tracks_info(&self) -> Result<(), Box<dyn Error>> {
-        println!("Loading your library information...");
-        let saved_tracks = self.paged_request::<SavedTrack>(SAVED_TRACKS)?;
-        println!("Library loaded.");
-
-        let mut artist_counter = HashMap::new();
-        let mut album_counter = HashMap::new();
+impl<'a> From<&Count<'a>> for NamedCounter<'a> {
+    fn from(count: &Count<'a>) -> Self {
+        NamedCounter {
+            id: count.id,
+            name: count.name,
+            songs: count.count,
+        }
+    }
+}
 
-        for track in &saved_tracks {
-            for artist in &track.track.artists {
-                artist_counter
-                    .entry(&artist.id)
-                    .and_modify(|c: &mut NamedCounter| c.counter += 1)
-                    .or_insert(NamedCounter {
-                        name: &artist.name,
-                        counter: 1,
-                    });
-            }
+#[derive(Serialize)]
+struct LibraryInfo<'a> {
+    songs: usize,
+    albums: usize,
+    artists: usize,
+    top_artists: Vec<NamedCounter<'a>>,
+}
 
-            album_counter
-                .entry(&track.track.album.id)
-                .and_modify(|c: &mut NamedCounter| c.counter += 1)
-                .or_insert(NamedCounter {
-                    name: &track.track.album.name,
-                    counter: 1,
-                });
-        }
+impl CmdHandler {
+    pub fn tracks_info(&self) -> Result<(), Error> {
+        self.client.info("Loading your library information...");
+        let saved_tracks = self.client.saved_tracks()?;
+        self.client.info("Library loaded.");
 
-        let top_artists = artist_counter
-            .into_iter()
-            .sorted_by(|(_, v1), (_, v2)| v2.counter.cmp(&v1.counter))
-            .collect::<Vec<_>>();
+        let stats = analysis::library_stats(saved_tracks.iter().map(|saved| &saved.track));
+        let top_artists = &stats.artists[..min(stats.artists.len(), 50)];
 
-        let albums = album_counter.into_iter().collect::<Vec<_>>();
+        if self.client.json_output() {
+            return self.print_json(&LibraryInfo {
+                songs: stats.songs,
+                albums: stats.albums.len(),
+                artists: stats.artists.len(),
+                top_artists: top_artists.iter().map(NamedCounter::from).collect(),
+            });
+        }
 
-        println!(
-            "{}",
+        self.client.info(
             style(format!(
                 "Your library contains {} songs from {} albums by {} artists.",
-                saved_tracks.len(),
-                albums.len(),
-                top_artists.len()
+                stats.songs,
+                stats.albums.len(),
+                stats.artists.len()
             ))
-            .cyan()
+            .cyan(),
         );
 
-        println!("{}", style("Most liked artists:").cyan());
-        for artist in &top_artists[..min(top_artists.len(), 50)] {
-            println!("{} - {} songs", artist.1.name, artist.1.counter);
+        self.client.info(style("Most liked artists:").cyan());
+        for artist in top_artists {
+            self.client
+                .info(format!("{} - {} songs", artist.name, artist.count));
         }
 
         Ok(())
@@ -1,21 +1,41 @@
-use super::spotify_api::{
-    endpoints::{ALL_PLAYLISTS, GET_USER, PLAYLIST_CREATION},
-    models::{Paging, PlaylistTrack, SimplifiedPlaylist, User},
+use super::{Cmd, CmdHandler};
+use crate::api::{
+    endpoints::{PLAYLIST_CREATION, PLAYLIST_TRACKS},
+    models::{PlaylistItem, PlaylistTrack, SimplifiedPlaylist},
 };
-use super::CmdHandler;
+use crate::auth;
+use crate::cache::Cache;
+use crate::client;
+use crate::error::Error;
+use clap::Args;
 use console::style;
-use dialoguer::{Confirmation, Input, Select};
-use indicatif::{ProgressBar, ProgressStyle};
-use serde::de::DeserializeOwned;
+use reqwest::Method;
+use serde::Serialize;
 use serde_json::json;
-use std::cmp::min;
-use std::error::Error;
+use std::fmt;
+
+/// Options allowing to create a playlist without being prompted
+#[derive(Args, Debug, Default)]
+pub struct PlaylistOptions {
+    /// Name of the playlist to create, or to update if you already have one with that name
+    #[arg(long)]
+    pub playlist_name: Option<String>,
+
+    /// Don't ask for confirmation before creating or updating the playlist
+    #[arg(long, short)]
+    pub yes: bool,
+}
 
 pub enum Command {
     TracksInfo,
     Duplicates,
     Decades,
     Genres,
+    Mirror,
+    Query,
+    RefreshCache,
+    Sync,
+    Logout,
     Exit,
 }
 
@@ -26,99 +46,113 @@ impl Command {
             Command::Duplicates,
             Command::Decades,
             Command::Genres,
+            Command::Mirror,
+            Command::Query,
+            Command::RefreshCache,
+            Command::Sync,
+            Command::Logout,
             Command::Exit,
         ]
     }
 }
 
-impl ToString for Command {
-    fn to_string(&self) -> String {
-        String::from(match &self {
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match &self {
             Command::TracksInfo => "Show information about top artists from your library",
             Command::Duplicates => "Remove duplicates from liked songs or from a playlist",
             Command::Decades => "Categorize your liked songs based on their release decade",
             Command::Genres => "Categorize your liked songs based on their artist's genre",
+            Command::Mirror => "Mirror your library into a local SQLite database",
+            Command::Query => "Run an SQL query against the mirrored library",
+            Command::RefreshCache => "Download your library again, replacing the cached copy",
+            Command::Sync => "Send the changes made to your library in offline mode",
+            Command::Logout => "Log out and forget saved credentials",
             Command::Exit => "Exit",
         })
     }
 }
 
 impl CmdHandler {
-    pub fn select_cmd(&self) -> Result<bool, Box<dyn Error>> {
+    pub fn select_cmd(&self) -> Result<bool, Error> {
         let commands = Command::commands();
-        let select = {
-            let mut select = Select::new();
-            select.with_prompt(&style("Select an action").cyan().to_string());
-            select.items(&commands);
-            select.default(0);
-            select
-        };
+        self.client.info("");
 
-        println!();
-
-        let answer = commands
-            .get(select.interact().unwrap_or(commands.len() - 1))
-            .unwrap();
+        let selection = self.client.ui().select(
+            &format!(
+                "Select an action [{}: {}]",
+                self.client.profile(),
+                self.user_name
+            ),
+            &commands.iter().map(Command::to_string).collect::<Vec<_>>(),
+            0,
+        );
+        // Exit if the prompt couldn't be shown
+        let answer = &commands[selection.unwrap_or(commands.len() - 1)];
 
         match answer {
-            Command::TracksInfo => self.tracks_info()?,
-            Command::Duplicates => self.duplicates()?,
-            Command::Decades => self.decades()?,
-            Command::Genres => self.genres()?,
+            Command::TracksInfo => self.run_cmd(&Cmd::TracksInfo)?,
+            Command::Duplicates => self.run_cmd(&Cmd::Duplicates(Default::default()))?,
+            Command::Decades => self.run_cmd(&Cmd::Decades(Default::default()))?,
+            Command::Genres => self.run_cmd(&Cmd::Genres(Default::default()))?,
+            Command::Mirror => self.run_cmd(&Cmd::Mirror(Default::default()))?,
+            Command::Query => self.run_cmd(&Cmd::Query(Default::default()))?,
+            Command::RefreshCache => self.run_cmd(&Cmd::RefreshCache)?,
+            Command::Sync => self.run_cmd(&Cmd::Sync)?,
+            Command::Logout => self.run_cmd(&Cmd::Logout)?,
             _ => (),
         };
 
-        Ok(if let Command::Exit = answer {
-            true
-        } else {
-            false
-        })
+        Ok(matches!(answer, Command::Logout | Command::Exit))
     }
 
-    pub fn paged_request<T: DeserializeOwned>(
-        &self,
-        endpoint: &str,
-    ) -> Result<Vec<T>, Box<dyn Error>> {
-        let mut next_url = Some(String::from(endpoint));
-        let mut data: Vec<T> = Vec::new();
-        let mut progress: Option<ProgressBar> = None;
-
-        while next_url.is_some() {
-            let resp = self
-                .client
-                .get(&next_url.unwrap())
-                .send()?
-                .error_for_status()?
-                .json::<Paging<T>>()?;
-
-            next_url = resp.next;
-            data.extend(resp.items);
+    /// Run a single command, options missing from the command line are prompted for
+    pub fn run_cmd(&self, cmd: &Cmd) -> Result<(), Error> {
+        if self.client.config().dry_run {
+            self.client
+                .info(style("Dry run, changes to your library will only be printed.").yellow());
+        } else if self.client.config().offline {
+            self.client.info(
+                style("Offline, changes to your library will be queued until `sync` is run.")
+                    .yellow(),
+            );
+        }
 
-            if progress.is_none() {
-                progress = Some(
-                    ProgressBar::new(resp.total).with_style(
-                        ProgressStyle::default_bar()
-                            .template("[{wide_bar}] {pos}/{len}")
-                            .progress_chars("=> "),
-                    ),
-                );
+        match cmd {
+            Cmd::TracksInfo => self.tracks_info(),
+            Cmd::Duplicates(options) => self.duplicates(options),
+            Cmd::Decades(options) => self.decades(options),
+            Cmd::Genres(options) => self.genres(options),
+            Cmd::Mirror(options) => self.mirror(options),
+            Cmd::Query(options) => self.query(options),
+            Cmd::RefreshCache => self.refresh_cache(),
+            Cmd::Sync => self.sync(),
+            Cmd::Logout => {
+                auth::logout(self.client.profile())?;
+                Cache::clear(self.client.profile())?;
+                self.client.info("Logged out, saved credentials removed.");
+                Ok(())
             }
-            progress.as_ref().unwrap().inc(resp.limit);
         }
-        progress.as_ref().unwrap().finish_and_clear();
+    }
 
-        Ok(data)
+    pub fn print_json<T: Serialize>(&self, result: &T) -> Result<(), Error> {
+        println!("{}", serde_json::to_string_pretty(result)?);
+        Ok(())
     }
 
-    fn open_playlist(&self, uri: &str) -> Result<(), Box<dyn Error>> {
-        if Confirmation::new()
-            .with_text(&style("Do you want to view it now?").cyan().to_string())
-            .default(false)
-            .interact()?
+    fn open_playlist(&self, uri: &str, options: &PlaylistOptions) -> Result<(), Error> {
+        if options.yes {
+            // Not running interactively, don't open anything
+            self.client.info(format!("See the playlist at: {}", uri));
+        } else if self
+            .client
+            .ui()
+            .confirm("Do you want to view it now?", false)?
         {
             match open::that(uri) {
-                Ok(_) => println!("Playlist opened in Spotify."),
-                Err(_) => println!("See the playlist at: {}", uri),
+                Ok(_) => self.client.info("Playlist opened in Spotify."),
+                Err(_) => self.client.info(format!("See the playlist at: {}", uri)),
             }
         }
         Ok(())
@@ -128,129 +162,139 @@ impl CmdHandler {
         &self,
         tracks: Vec<&String>,
         default_name: &str,
-    ) -> Result<(), Box<dyn Error>> {
-        let name = Input::<String>::new()
-            .with_prompt(
-                &style("Select the name of your new playlist")
-                    .cyan()
-                    .to_string(),
-            )
-            .default(String::from(&default_name[..min(default_name.len(), 100)]))
-            .interact()?;
+        options: &PlaylistOptions,
+    ) -> Result<(), Error> {
+        let name = match &options.playlist_name {
+            Some(name) => name.to_owned(),
+            None => self.client.ui().input(
+                "Select the name of your new playlist",
+                Some(&default_name.chars().take(100).collect::<String>()),
+            )?,
+        };
 
-        let name = &name[..min(name.len(), 100)];
+        // Counted in characters, cutting at a byte could split one
+        let name = &name.chars().take(100).collect::<String>()[..];
 
-        let user_id = self
-            .client
-            .get(GET_USER)
-            .send()?
-            .error_for_status()?
-            .json::<User>()?
-            .id;
+        let user_id = self.client.current_user()?.id;
         let playlists = self
-            .paged_request::<SimplifiedPlaylist>(ALL_PLAYLISTS)?
+            .client
+            .playlists()?
             .into_iter()
             .filter(|playlist| playlist.owner.id == user_id && playlist.name == name)
             .collect::<Vec<_>>();
 
         if playlists.len() == 1 {
             let current = &playlists[0];
-            println!(
+            self.client.info(format!(
                 "You are going to update an existing \"{}\" playlist containing {} songs to have {} songs.",
                 current.name, current.tracks.total, tracks.len()
-            );
-            if Confirmation::new()
-                .with_text(&style("Do you want to proceed?").cyan().to_string())
-                .interact()?
-            {
-                println!("Updating the playlist...");
-                println!("Fetching current playlist information.");
-                let current_tracks = self.paged_request::<PlaylistTrack>(&current.tracks.href)?;
-                println!("Playlist information downloaded.");
+            ));
+            if options.yes || self.client.ui().confirm("Do you want to proceed?", true)? {
+                self.client.info("Updating the playlist...");
+                self.client.info("Fetching current playlist information.");
+                let current_tracks = self.client.playlist_tracks(current)?;
+                self.client.info("Playlist information downloaded.");
+                let local_files = current_tracks
+                    .iter()
+                    .filter(|item| matches!(item.track, Some(PlaylistItem::Local(_))))
+                    .count();
+                if local_files > 0 {
+                    self.client.info(format!(
+                        "The {} local files of the playlist are kept, Spotify doesn't allow changing them.",
+                        local_files
+                    ));
+                }
                 let current_uris = current_tracks
                     .iter()
-                    .map(|t| &t.track.uri)
+                    .filter_map(PlaylistTrack::uri)
                     .collect::<Vec<_>>();
-                let new_uris = tracks;
+                let new_uris = tracks.iter().map(|uri| &uri[..]).collect::<Vec<_>>();
 
                 let uris_to_delete = current_uris
                     .iter()
-                    .filter(|&&c_uri| !new_uris.iter().any(|&n_uri| c_uri == n_uri))
+                    .filter(|uri| !new_uris.contains(uri))
                     .collect::<Vec<_>>();
 
                 let uris_to_add = new_uris
                     .iter()
-                    .filter(|&&n_uri| !current_uris.iter().any(|&c_uri| n_uri == c_uri))
+                    .filter(|uri| !current_uris.contains(uri))
                     .collect::<Vec<_>>();
 
-                println!(
+                self.client.info(format!(
                     "Removing tracks ({}) from playlist...",
                     uris_to_delete.len()
-                );
+                ));
                 let delete_chunks = uris_to_delete.chunks(100);
                 for chunk in delete_chunks {
-                    self.client
-                        .delete(&current.tracks.href)
-                        .json(&json!({
+                    self.client.modify(
+                        Method::DELETE,
+                        &current.tracks.href,
+                        &json!({
                             "tracks": chunk.iter().map(|c| {
                                     json!({
                                         "uri": &c
                                     })
                                 }).collect::<Vec<_>>(),
                             "snapshot_id": &current.snapshot_id
-                        }))
-                        .send()?
-                        .error_for_status()?;
+                        }),
+                    )?;
                 }
-                println!("Tracks removed successfully.");
+                self.client.info("Tracks removed successfully.");
 
-                println!("Adding tracks ({}) to the playlist...", uris_to_add.len());
+                self.client.info(format!(
+                    "Adding tracks ({}) to the playlist...",
+                    uris_to_add.len()
+                ));
                 let add_chunks = uris_to_add.chunks(100);
                 for chunk in add_chunks {
-                    self.client
-                        .post(&current.tracks.href)
-                        .json(&json!({ "uris": &chunk }))
-                        .send()?
-                        .error_for_status()?;
+                    self.client.modify(
+                        Method::POST,
+                        &current.tracks.href,
+                        &json!({ "uris": &chunk }),
+                    )?;
                 }
-                println!("Tracks added successfully.");
-                println!("Playlist updated.");
-                self.open_playlist(&current.uri)?;
+                self.client.info("Tracks added successfully.");
+                self.client.info("Playlist updated.");
+                self.open_playlist(&current.uri, options)?;
             } else {
-                println!("Didn't update the playlist.");
+                self.client.info("Didn't update the playlist.");
             }
             Ok(())
         } else {
-            println!(
+            self.client.info(format!(
                 "You are going to create a \"{}\" playlist containing {} songs.",
                 name,
                 tracks.len()
-            );
-            if Confirmation::new()
-                .with_text(&style("Do you want to proceed?").cyan().to_string())
-                .interact()?
-            {
-                println!("Creating the playlist...");
+            ));
+            if options.yes || self.client.ui().confirm("Do you want to proceed?", true)? {
+                self.client.info("Creating the playlist...");
+                // On a dry run nothing gets created, so there is no playlist to add the songs to
                 let playlist = self
                     .client
-                    .post(&PLAYLIST_CREATION.replace("{user_id}", &user_id))
-                    .json(&json!({ "name": &name }))
-                    .send()?
-                    .error_for_status()?
-                    .json::<SimplifiedPlaylist>()?;
-                println!("Adding songs to the playlist...");
+                    .modify(
+                        Method::POST,
+                        &PLAYLIST_CREATION.replace("{user_id}", &user_id),
+                        &json!({ "name": &name }),
+                    )?
+                    .map(|response| {
+                        client::decode::<SimplifiedPlaylist>(PLAYLIST_CREATION, response)
+                    })
+                    .transpose()?;
+                let tracks_href = playlist
+                    .as_ref()
+                    .map_or(PLAYLIST_TRACKS, |playlist| &playlist.tracks.href);
+                self.client.info("Adding songs to the playlist...");
                 let chunks = tracks.chunks(100);
                 for chunk in chunks {
                     self.client
-                        .post(&playlist.tracks.href)
-                        .json(&json!({ "uris": &chunk }))
-                        .send()?
-                        .error_for_status()?;
+                        .modify(Method::POST, tracks_href, &json!({ "uris": &chunk }))?;
+                }
+                self.client.info("Playlist created.");
+                if let Some(playlist) = playlist {
+                    self.open_playlist(&playlist.uri, options)?;
                 }
-                println!("Playlist created.");
-                self.open_playlist(&playlist.uri)?;
             } else {
-                println!("Didn't create the playlist.");
+                self.client.info("Didn't create the playlist.");
             }
             Ok(())
         }
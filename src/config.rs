@@ -0,0 +1,165 @@
+use crate::error::Error;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "spotify-tools";
+const CONFIG_FILE: &str = "config.json";
+const CLIENT_ID: &str = "bda57df0ca3244ea96cc8f16dfe04ab7";
+const PORT: u16 = 8000;
+const CALLBACK: &str = "/callback";
+const API_URL: &str = "https://api.spotify.com/v1";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Human readable text
+    Text,
+    /// Machine readable JSON document
+    Json,
+}
+
+/// Settings after merging the config file, environment variables and CLI flags (in ascending priority)
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub client_id: String,
+    pub port: u16,
+    pub callback: String,
+    pub auto_port: bool,
+    pub headless: bool,
+    pub profile: Option<String>,
+    /// Base URL of the Web API, without a trailing slash
+    pub api_url: String,
+    /// Reuse the library downloaded by previous runs
+    pub cache: bool,
+    // The rest is only given on the command line, never in the config file
+    /// Token used instead of logging in, it's never saved nor refreshed
+    pub access_token: Option<String>,
+    /// Read the library from the cache only, changes are queued instead of being sent
+    pub offline: bool,
+    /// Only available as a flag, so that it always has to be explicitly requested
+    pub dry_run: bool,
+    pub output: Output,
+}
+
+/// Settings that can be given in the config file, every field is optional.
+/// Also used for the values given on the command line, which take priority over the file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub client_id: Option<String>,
+    pub port: Option<u16>,
+    pub callback: Option<String>,
+    pub auto_port: Option<bool>,
+    pub headless: Option<bool>,
+    pub profile: Option<String>,
+    pub api_url: Option<String>,
+    pub cache: Option<bool>,
+}
+
+impl Settings {
+    /// Read the config file, the default one doesn't have to exist
+    pub fn read(path: Option<&Path>) -> Result<Settings, Error> {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => (config_dir()?.join(CONFIG_FILE), false),
+        };
+        match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|err| {
+                Error::Config(format!("Invalid config file {}: {}", path.display(), err))
+            }),
+            // The default config file is optional, but one given explicitly has to exist
+            Err(err) if err.kind() == ErrorKind::NotFound && !explicit => Ok(Settings::default()),
+            Err(err) => Err(Error::Config(format!(
+                "Could not read config file {}: {}",
+                path.display(),
+                err
+            ))),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            client_id: String::from(CLIENT_ID),
+            port: PORT,
+            callback: String::from(CALLBACK),
+            auto_port: false,
+            headless: false,
+            profile: None,
+            api_url: String::from(API_URL),
+            cache: true,
+            access_token: None,
+            offline: false,
+            dry_run: false,
+            output: Output::Text,
+        }
+    }
+}
+
+impl Config {
+    /// Merge the settings given on the command line with the config file and the defaults
+    pub fn load(path: Option<&Path>, args: Settings) -> Result<Config, Error> {
+        let file = Settings::read(path)?;
+
+        let callback = args
+            .callback
+            .or(file.callback)
+            .unwrap_or_else(|| String::from(CALLBACK));
+        if !callback.starts_with('/') {
+            return Err(Error::Config(format!(
+                "Callback path \"{}\" has to start with a slash.",
+                callback
+            )));
+        }
+
+        let api_url = args
+            .api_url
+            .or(file.api_url)
+            .unwrap_or_else(|| String::from(API_URL));
+        if !api_url.starts_with("http://") && !api_url.starts_with("https://") {
+            return Err(Error::Config(format!(
+                "API URL \"{}\" has to start with http:// or https://.",
+                api_url
+            )));
+        }
+
+        Ok(Config {
+            client_id: args
+                .client_id
+                .or(file.client_id)
+                .unwrap_or_else(|| String::from(CLIENT_ID)),
+            port: args.port.or(file.port).unwrap_or(PORT),
+            callback,
+            auto_port: args.auto_port.or(file.auto_port).unwrap_or(false),
+            headless: args.headless.or(file.headless).unwrap_or(false),
+            profile: args.profile.or(file.profile),
+            api_url: String::from(api_url.trim_end_matches('/')),
+            cache: args.cache.or(file.cache).unwrap_or(true),
+            access_token: None,
+            offline: false,
+            dry_run: false,
+            output: Output::Text,
+        })
+    }
+}
+
+fn app_dir(base: Option<PathBuf>) -> Result<PathBuf, Error> {
+    Ok(base
+        .ok_or_else(|| {
+            Error::Config(String::from(
+                "Could not determine the user's config/cache directory.",
+            ))
+        })?
+        .join(APP_DIR))
+}
+
+pub fn config_dir() -> Result<PathBuf, Error> {
+    app_dir(dirs::config_dir())
+}
+
+pub fn cache_dir() -> Result<PathBuf, Error> {
+    app_dir(dirs::cache_dir())
+}
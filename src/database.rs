@@ -0,0 +1,298 @@
+//! SQLite mirror of the library, so that questions no command covers can be answered with SQL.
+//! The database is recreated by every mirror, its schema is `SCHEMA`.
+use crate::api::models::{FullArtist, PlaylistTrack, SavedTrack, SimplifiedPlaylist, Track};
+use crate::config;
+use crate::error::Error;
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, Transaction};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DATABASE_DIR: &str = "library";
+/// Bumped whenever the schema changes, stored as the `user_version` of the database
+const SCHEMA_VERSION: u32 = 2;
+
+/// Tables of the mirror. Positions start at 0, in the order the API returns the items.
+pub const SCHEMA: &str = "
+CREATE TABLE albums (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- album, single or compilation
+    album_type TEXT NOT NULL,
+    -- YYYY, YYYY-MM or YYYY-MM-DD depending on the precision known to Spotify
+    release_date TEXT NOT NULL,
+    -- year, month or day
+    release_date_precision TEXT NOT NULL,
+    release_year INTEGER,
+    total_tracks INTEGER NOT NULL,
+    -- URL of the largest cover art
+    image_url TEXT
+);
+CREATE TABLE artists (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL
+);
+-- Genres are only known for the artists Spotify returned information about
+CREATE TABLE artist_genres (
+    artist_id TEXT NOT NULL REFERENCES artists (id),
+    genre TEXT NOT NULL,
+    PRIMARY KEY (artist_id, genre)
+);
+CREATE TABLE tracks (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    album_id TEXT NOT NULL REFERENCES albums (id),
+    duration_ms INTEGER NOT NULL,
+    -- From 0 to 100
+    popularity INTEGER NOT NULL,
+    -- 0 or 1
+    explicit INTEGER NOT NULL,
+    isrc TEXT,
+    disc_number INTEGER NOT NULL,
+    track_number INTEGER NOT NULL
+);
+-- The artist at position 0 is the main one
+CREATE TABLE track_artists (
+    track_id TEXT NOT NULL REFERENCES tracks (id),
+    artist_id TEXT NOT NULL REFERENCES artists (id),
+    position INTEGER NOT NULL,
+    PRIMARY KEY (track_id, position)
+);
+-- Liked songs, the newest one at position 0
+CREATE TABLE saved_tracks (
+    position INTEGER PRIMARY KEY,
+    track_id TEXT NOT NULL REFERENCES tracks (id),
+    -- ISO 8601 timestamp
+    added_at TEXT NOT NULL
+);
+-- Playlists followed by the user, including the ones they don't own
+CREATE TABLE playlists (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    owner_id TEXT NOT NULL,
+    snapshot_id TEXT NOT NULL,
+    uri TEXT NOT NULL
+);
+-- Only Spotify tracks are stored, episodes, local files and unavailable items leave gaps in the positions
+CREATE TABLE playlist_tracks (
+    playlist_id TEXT NOT NULL REFERENCES playlists (id),
+    position INTEGER NOT NULL,
+    track_id TEXT NOT NULL REFERENCES tracks (id),
+    -- ISO 8601 timestamp, NULL for items added long ago
+    added_at TEXT,
+    -- User ID
+    added_by TEXT,
+    PRIMARY KEY (playlist_id, position)
+);
+";
+
+const TABLES: [&str; 8] = [
+    "playlist_tracks",
+    "playlists",
+    "saved_tracks",
+    "track_artists",
+    "tracks",
+    "artist_genres",
+    "artists",
+    "albums",
+];
+
+/// Everything that gets mirrored
+pub struct Library<'a> {
+    pub saved_tracks: &'a [SavedTrack],
+    pub playlists: &'a [(SimplifiedPlaylist, Vec<PlaylistTrack>)],
+    /// Full information about the artists, for their genres
+    pub artists: &'a [FullArtist],
+}
+
+/// Rows returned by a query, with their values converted to JSON
+#[derive(Debug)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+pub struct Database {
+    connection: Connection,
+}
+
+/// Default location of the database of a profile
+pub fn database_path(profile: &str) -> Result<PathBuf, Error> {
+    Ok(config::cache_dir()?
+        .join(DATABASE_DIR)
+        .join(profile)
+        .with_extension("sqlite"))
+}
+
+impl Database {
+    /// Open the database, creating it if needed
+    pub fn create(path: &Path) -> Result<Database, Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Database {
+            connection: Connection::open(path)?,
+        })
+    }
+
+    /// Open an existing database, it has to be mirrored first
+    pub fn open(path: &Path) -> Result<Database, Error> {
+        if !path.exists() {
+            return Err(Error::Input(format!(
+                "There is no database at {}, run `mirror` first.",
+                path.display()
+            )));
+        }
+        Ok(Database {
+            connection: Connection::open(path)?,
+        })
+    }
+
+    /// Replace the contents of the database with the library
+    pub fn mirror(&mut self, library: &Library) -> Result<(), Error> {
+        let transaction = self.connection.transaction()?;
+        for table in &TABLES {
+            transaction.execute(&format!("DROP TABLE IF EXISTS {}", table), [])?;
+        }
+        transaction.execute_batch(SCHEMA)?;
+        transaction.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        for (position, saved) in library.saved_tracks.iter().enumerate() {
+            insert_track(&transaction, &saved.track)?;
+            transaction.execute(
+                "INSERT INTO saved_tracks (position, track_id, added_at) VALUES (?1, ?2, ?3)",
+                params![position, saved.track.id, saved.added_at],
+            )?;
+        }
+        for (playlist, tracks) in library.playlists {
+            transaction.execute(
+                "INSERT OR REPLACE INTO playlists (id, name, owner_id, snapshot_id, uri) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    playlist.id,
+                    playlist.name,
+                    playlist.owner.id,
+                    playlist.snapshot_id,
+                    playlist.uri
+                ],
+            )?;
+            for (position, item) in tracks.iter().enumerate() {
+                if let Some(track) = item.track() {
+                    insert_track(&transaction, track)?;
+                    transaction.execute(
+                        "INSERT INTO playlist_tracks (playlist_id, position, track_id, added_at, added_by) \
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![
+                            playlist.id,
+                            position,
+                            track.id,
+                            item.added_at,
+                            item.added_by.as_ref().map(|user| &user.id)
+                        ],
+                    )?;
+                }
+            }
+        }
+        for artist in library.artists {
+            transaction.execute(
+                "INSERT OR IGNORE INTO artists (id, name) VALUES (?1, ?2)",
+                params![artist.id, artist.name],
+            )?;
+            for genre in &artist.genres {
+                transaction.execute(
+                    "INSERT OR IGNORE INTO artist_genres (artist_id, genre) VALUES (?1, ?2)",
+                    params![artist.id, genre],
+                )?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Run any SQL statement, the rows it returns are collected
+    pub fn query(&self, sql: &str) -> Result<QueryResult, Error> {
+        let failed = |err: rusqlite::Error| Error::Input(format!("Query failed: {}", err));
+        let mut statement = self.connection.prepare(sql).map_err(failed)?;
+        let columns = statement
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        let mut rows = statement.query([]).map_err(failed)?;
+        let mut values = Vec::new();
+        while let Some(row) = rows.next().map_err(failed)? {
+            values.push(
+                (0..columns.len())
+                    .map(|index| row.get_ref(index).map(to_json))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(failed)?,
+            );
+        }
+        Ok(QueryResult {
+            columns,
+            rows: values,
+        })
+    }
+}
+
+/// Insert a track with its album and artists, unless it's already there
+fn insert_track(transaction: &Transaction, track: &Track) -> Result<(), Error> {
+    let album = &track.album;
+    transaction.execute(
+        "INSERT OR IGNORE INTO albums (id, name, album_type, release_date, release_date_precision, \
+         release_year, total_tracks, image_url) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            album.id,
+            album.name,
+            album.album_type,
+            album.release_date,
+            album.release_date_precision,
+            album.release_date.get(0..4).and_then(|year| year.parse::<u32>().ok()),
+            album.total_tracks,
+            album.images.first().map(|image| &image.url)
+        ],
+    )?;
+    let inserted = transaction.execute(
+        "INSERT OR IGNORE INTO tracks (id, name, uri, album_id, duration_ms, popularity, explicit, \
+         isrc, disc_number, track_number) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            track.id,
+            track.name,
+            track.uri,
+            album.id,
+            track.duration_ms,
+            track.popularity,
+            track.explicit,
+            track.external_ids.isrc,
+            track.disc_number,
+            track.track_number
+        ],
+    )?;
+    if inserted == 0 {
+        return Ok(());
+    }
+    for (position, artist) in track.artists.iter().enumerate() {
+        transaction.execute(
+            "INSERT OR IGNORE INTO artists (id, name) VALUES (?1, ?2)",
+            params![artist.id, artist.name],
+        )?;
+        transaction.execute(
+            "INSERT INTO track_artists (track_id, artist_id, position) VALUES (?1, ?2, ?3)",
+            params![track.id, artist.id, position],
+        )?;
+    }
+    Ok(())
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(int) => Value::from(int),
+        ValueRef::Real(real) => Value::from(real),
+        ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text)),
+        ValueRef::Blob(blob) => Value::from(base64::encode(blob)),
+    }
+}
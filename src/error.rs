@@ -0,0 +1,150 @@
+use reqwest::StatusCode;
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Logging in or refreshing the token failed
+    Auth(String),
+    /// Spotify responded with an error status, message is taken from the error body if present
+    Http {
+        status: StatusCode,
+        endpoint: String,
+        message: Option<String>,
+    },
+    /// Response didn't match the expected model
+    Decode {
+        endpoint: String,
+        source: serde_json::Error,
+    },
+    /// Request couldn't be sent or the response couldn't be read
+    Network(reqwest::Error),
+    /// Invalid config file, flags or environment variables
+    Config(String),
+    /// Invalid value given as an option or in a prompt, e.g. a playlist that doesn't exist
+    Input(String),
+    /// Reading or writing the local database failed
+    Database(rusqlite::Error),
+    /// Data needed by the command isn't cached, so it can't be used in offline mode
+    Offline(String),
+    /// User aborted a prompt
+    Cancelled,
+    Io(io::Error),
+}
+
+impl Error {
+    /// Exit code of the process when the error is not handled
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) | Error::Database(_) => 1,
+            Error::Config(_) | Error::Input(_) => 2,
+            Error::Auth(_) => 3,
+            Error::Http { status, .. } if *status == StatusCode::UNAUTHORIZED => 3,
+            Error::Http { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS => 5,
+            Error::Http { status, .. } if status.is_server_error() => 5,
+            Error::Http { .. } => 4,
+            Error::Network(_) => 5,
+            Error::Decode { .. } => 6,
+            Error::Offline(_) => 7,
+            Error::Cancelled => 130,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Auth(message) => write!(
+                f,
+                "Authorization failed: {} Try logging in again, or log out first if the problem persists.",
+                message
+            ),
+            Error::Http {
+                status,
+                endpoint,
+                message,
+            } => {
+                let details = message
+                    .as_ref()
+                    .map_or_else(String::new, |message| format!(" ({})", message));
+                match *status {
+                    StatusCode::UNAUTHORIZED => write!(
+                        f,
+                        "Spotify rejected your credentials{}. Log out and log in again.",
+                        details
+                    ),
+                    StatusCode::FORBIDDEN => write!(
+                        f,
+                        "Spotify denied access to {}{}. Make sure you own what you are modifying and that the login granted all permissions.",
+                        endpoint, details
+                    ),
+                    StatusCode::NOT_FOUND => write!(
+                        f,
+                        "{} was not found{}. It might have been removed in the meantime.",
+                        endpoint, details
+                    ),
+                    StatusCode::TOO_MANY_REQUESTS => write!(
+                        f,
+                        "Spotify is limiting the number of requests{}. Wait a while before trying again.",
+                        details
+                    ),
+                    status if status.is_server_error() => write!(
+                        f,
+                        "Spotify failed to handle the request to {} with {}{}. Try again later.",
+                        endpoint, status, details
+                    ),
+                    status => write!(
+                        f,
+                        "Request to {} failed with {}{}.",
+                        endpoint, status, details
+                    ),
+                }
+            }
+            Error::Decode { endpoint, source } => write!(
+                f,
+                "Unexpected response from {}: {}. The Spotify API might have changed, please report this issue.",
+                endpoint, source
+            ),
+            Error::Network(source) => write!(
+                f,
+                "Could not connect to Spotify: {}. Check your internet connection.",
+                source
+            ),
+            Error::Config(message) | Error::Input(message) | Error::Offline(message) => {
+                f.write_str(message)
+            }
+            Error::Database(source) => write!(f, "Database error: {}", source),
+            Error::Cancelled => f.write_str("Cancelled."),
+            Error::Io(source) => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        match err.kind() {
+            // Raised by the prompts when the user aborts them
+            io::ErrorKind::Interrupted | io::ErrorKind::UnexpectedEof => Error::Cancelled,
+            _ => Error::Io(err),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Error {
+        Error::Network(err)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Database(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Io(err.into())
+    }
+}
@@ -0,0 +1,21 @@
+//! Tools for manipulating a Spotify library.
+//!
+//! The [`Client`] gives access to the Web API, the [`analysis`] functions work on the fetched
+//! tracks without any I/O, and [`cmds`] are the commands of the `spotify-tools` binary, prompting
+//! the user through a [`ui::Ui`].
+
+pub mod analysis;
+pub mod api;
+pub mod auth;
+pub mod cache;
+pub mod client;
+pub mod cmds;
+pub mod config;
+pub mod database;
+pub mod error;
+pub mod queue;
+pub mod ui;
+
+pub use client::Client;
+pub use config::Config;
+pub use error::Error;
@@ -1,26 +1,49 @@
-use std::error::Error;
+use clap::Parser;
+use console::style;
+use spotify_tools::cache::Cache;
+use spotify_tools::cmds::{Cmd, CmdHandler};
+use spotify_tools::config::Output;
+use spotify_tools::error::Error;
+use spotify_tools::ui::TerminalUi;
+use spotify_tools::{auth, Client};
+use std::process;
 
-mod auth;
-mod cmds;
+mod cli;
 
-fn run() -> Result<(), Box<dyn Error>> {
-    let token = auth::authorize()?;
-    let client = cmds::CmdHandler::new(&token)?;
+fn run() -> Result<(), Error> {
+    let args = cli::Args::parse();
+    let config = args.config()?;
+    // Only the interactive menu may ask for the profile, commands can run unattended
+    let profile = auth::select_profile(&config, args.command.is_none())?;
 
-    loop {
-        // Break loop on true (if should exit)
-        if let true = client.select_cmd()? {
-            break;
-        }
+    // Logging out doesn't require being logged in
+    if let Some(Cmd::Logout) = args.command {
+        auth::logout(&profile)?;
+        Cache::clear(&profile)?;
+        eprintln!("Logged out, saved credentials removed.");
+        return Ok(());
+    }
+
+    let token = auth::login(&config, &profile)?;
+    let ui = TerminalUi::new(config.output == Output::Json);
+    let client = CmdHandler::new(Client::new(config, profile, token, Box::new(ui)))?;
+
+    match &args.command {
+        Some(cmd) => client.run_cmd(cmd)?,
+        None => loop {
+            // Break loop on true (if should exit)
+            if client.select_cmd()? {
+                break;
+            }
+        },
     }
 
     Ok(())
 }
 
 fn main() {
-    if cfg!(debug_assertions) {
-        run().unwrap();
-    } else {
-        run().unwrap_or_else(|_| println!("Unexpected error occurred."));
+    if let Err(err) = run() {
+        eprintln!("{} {}", style("Error:").red(), err);
+        process::exit(err.exit_code());
     }
 }
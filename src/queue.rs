@@ -0,0 +1,104 @@
+//! Changes to the library made in offline mode, kept on disk until they are sent by `sync`.
+use crate::api::endpoints::PLAYLIST_TRACKS;
+use crate::config;
+use crate::error::Error;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+const QUEUE_DIR: &str = "queue";
+
+/// Request modifying the library, as it would have been sent
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub method: String,
+    /// Endpoint path or absolute URL
+    pub url: String,
+    pub body: Value,
+}
+
+impl Change {
+    pub fn new(method: &Method, url: &str, body: &Value) -> Change {
+        Change {
+            method: method.to_string(),
+            url: String::from(url),
+            body: body.clone(),
+        }
+    }
+
+    pub fn method(&self) -> Result<Method, Error> {
+        Method::from_bytes(self.method.as_bytes()).map_err(|_| {
+            Error::Config(format!(
+                "Queued change has an invalid method \"{}\".",
+                self.method
+            ))
+        })
+    }
+
+    pub fn creates_playlist(&self) -> bool {
+        self.method == "POST" && self.url.starts_with("/users/") && self.url.ends_with("/playlists")
+    }
+}
+
+/// Changes in the order they were made
+#[derive(Serialize, Deserialize, Default)]
+pub struct Queue {
+    pub changes: Vec<Change>,
+}
+
+fn queue_path(profile: &str) -> Result<PathBuf, Error> {
+    Ok(config::cache_dir()?
+        .join(QUEUE_DIR)
+        .join(profile)
+        .with_extension("json"))
+}
+
+impl Queue {
+    /// Load the queued changes of a profile.
+    /// Unlike the cache, an unreadable queue is an error, so that changes are never silently lost.
+    pub fn load(profile: &str) -> Result<Queue, Error> {
+        let path = queue_path(profile)?;
+        if !path.exists() {
+            return Ok(Queue::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Save the queue, the file is removed once it's empty
+    pub fn save(&self, profile: &str) -> Result<(), Error> {
+        let path = queue_path(profile)?;
+        if self.changes.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the same change is already queued, e.g. by running a command twice.
+    /// Songs added to playlists created offline aren't compared, their URL doesn't tell the playlists apart.
+    pub fn contains(&self, change: &Change) -> bool {
+        change.url != PLAYLIST_TRACKS && self.changes.contains(change)
+    }
+
+    /// Songs added to a playlist created offline are queued without knowing its ID.
+    /// Once it's created, point them to its tracks, up to the next created playlist.
+    pub fn resolve_created(&mut self, tracks_href: &str) {
+        for change in self
+            .changes
+            .iter_mut()
+            .take_while(|change| !change.creates_playlist())
+        {
+            if change.url == PLAYLIST_TRACKS {
+                change.url = String::from(tracks_href);
+            }
+        }
+    }
+}
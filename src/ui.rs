@@ -0,0 +1,37 @@
+//! Interaction with the user, kept behind a trait so that commands can run without a terminal.
+//! Choosing the profile and pasting the login redirect happen before the commands run,
+//! they always prompt on the terminal instead (see `auth::select_profile` and `auth::authorize`).
+use crate::error::Error;
+
+mod scripted;
+mod terminal;
+
+pub use scripted::{Answer, ScriptedUi};
+pub use terminal::TerminalUi;
+
+/// Prompts and output of the commands.
+/// Prompts are given as plain text, styling them is up to the implementation.
+pub trait Ui: Send + Sync {
+    /// Choose one of the items, returns its index
+    fn select(&self, prompt: &str, items: &[String], default: usize) -> Result<usize, Error>;
+
+    /// Choose any number of the items, returns their indices
+    fn multi_select(&self, prompt: &str, items: &[String]) -> Result<Vec<usize>, Error>;
+
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool, Error>;
+
+    fn input(&self, prompt: &str, default: Option<&str>) -> Result<String, Error>;
+
+    /// Show the progress of a task made of `len` steps
+    fn progress(&self, len: u64) -> Box<dyn Progress>;
+
+    /// Show a status message or a result in text form
+    fn message(&self, message: &str);
+}
+
+pub trait Progress: Send + Sync {
+    fn inc(&self, delta: u64);
+
+    /// Remove the progress indicator once the task is done
+    fn finish(&self);
+}
@@ -0,0 +1,135 @@
+use super::{Progress, Ui};
+use crate::error::Error;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Answer to a single prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Answer {
+    Select(usize),
+    MultiSelect(Vec<usize>),
+    Confirm(bool),
+    Input(String),
+    /// Accept the default of whatever is asked, no items of a multi-select
+    Default,
+}
+
+#[derive(Default)]
+struct Script {
+    answers: VecDeque<Answer>,
+    prompts: Vec<String>,
+    messages: Vec<String>,
+}
+
+/// Answers prompts from a script instead of asking, and records everything shown.
+/// Clones share the same script, so one can be kept to inspect the run afterwards.
+#[derive(Clone, Default)]
+pub struct ScriptedUi {
+    script: Arc<Mutex<Script>>,
+}
+
+impl ScriptedUi {
+    pub fn new(answers: impl IntoIterator<Item = Answer>) -> ScriptedUi {
+        ScriptedUi {
+            script: Arc::new(Mutex::new(Script {
+                answers: answers.into_iter().collect(),
+                ..Script::default()
+            })),
+        }
+    }
+
+    /// Prompts asked so far
+    pub fn prompts(&self) -> Vec<String> {
+        self.script.lock().unwrap().prompts.clone()
+    }
+
+    /// Messages shown so far
+    pub fn messages(&self) -> Vec<String> {
+        self.script.lock().unwrap().messages.clone()
+    }
+
+    /// Answers not used by the prompts
+    pub fn remaining(&self) -> Vec<Answer> {
+        self.script
+            .lock()
+            .unwrap()
+            .answers
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn answer(&self, prompt: &str) -> Result<Answer, Error> {
+        let mut script = self.script.lock().unwrap();
+        script.prompts.push(String::from(prompt));
+        script.answers.pop_front().ok_or_else(|| {
+            Error::Input(format!(
+                "No answer for \"{}\", give it as an option instead.",
+                prompt
+            ))
+        })
+    }
+}
+
+fn unexpected(prompt: &str, answer: Answer) -> Error {
+    Error::Input(format!(
+        "Answer {:?} doesn't fit the prompt \"{}\".",
+        answer, prompt
+    ))
+}
+
+impl Ui for ScriptedUi {
+    fn select(&self, prompt: &str, items: &[String], default: usize) -> Result<usize, Error> {
+        match self.answer(prompt)? {
+            Answer::Select(index) if index < items.len() => Ok(index),
+            Answer::Default => Ok(default),
+            answer => Err(unexpected(prompt, answer)),
+        }
+    }
+
+    fn multi_select(&self, prompt: &str, items: &[String]) -> Result<Vec<usize>, Error> {
+        match self.answer(prompt)? {
+            Answer::MultiSelect(indices) if indices.iter().all(|&index| index < items.len()) => {
+                Ok(indices)
+            }
+            Answer::Default => Ok(Vec::new()),
+            answer => Err(unexpected(prompt, answer)),
+        }
+    }
+
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool, Error> {
+        match self.answer(prompt)? {
+            Answer::Confirm(confirmed) => Ok(confirmed),
+            Answer::Default => Ok(default),
+            answer => Err(unexpected(prompt, answer)),
+        }
+    }
+
+    fn input(&self, prompt: &str, default: Option<&str>) -> Result<String, Error> {
+        match (self.answer(prompt)?, default) {
+            (Answer::Input(text), _) => Ok(text),
+            (Answer::Default, Some(default)) => Ok(String::from(default)),
+            (answer, _) => Err(unexpected(prompt, answer)),
+        }
+    }
+
+    fn progress(&self, _len: u64) -> Box<dyn Progress> {
+        Box::new(NoProgress)
+    }
+
+    fn message(&self, message: &str) {
+        self.script
+            .lock()
+            .unwrap()
+            .messages
+            .push(String::from(message));
+    }
+}
+
+struct NoProgress;
+
+impl Progress for NoProgress {
+    fn inc(&self, _delta: u64) {}
+
+    fn finish(&self) {}
+}
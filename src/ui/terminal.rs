@@ -0,0 +1,79 @@
+use super::{Progress, Ui};
+use crate::error::Error;
+use console::style;
+use dialoguer::{Checkboxes, Confirmation, Input, Select};
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Interactive prompts in the terminal
+pub struct TerminalUi {
+    /// With JSON output stdout is reserved for the result, so messages go to stderr instead
+    json_output: bool,
+}
+
+impl TerminalUi {
+    pub fn new(json_output: bool) -> TerminalUi {
+        TerminalUi { json_output }
+    }
+}
+
+impl Ui for TerminalUi {
+    fn select(&self, prompt: &str, items: &[String], default: usize) -> Result<usize, Error> {
+        Ok(Select::new()
+            .with_prompt(&style(prompt).cyan().to_string())
+            .items(items)
+            .default(default)
+            .interact()?)
+    }
+
+    fn multi_select(&self, prompt: &str, items: &[String]) -> Result<Vec<usize>, Error> {
+        Ok(Checkboxes::new()
+            .with_prompt(&style(prompt).cyan().to_string())
+            .items(&items.iter().map(|item| &item[..]).collect::<Vec<_>>())
+            .paged(true)
+            .interact()?)
+    }
+
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool, Error> {
+        Ok(Confirmation::new()
+            .with_text(&style(prompt).cyan().to_string())
+            .default(default)
+            .interact()?)
+    }
+
+    fn input(&self, prompt: &str, default: Option<&str>) -> Result<String, Error> {
+        let mut input = Input::<String>::new();
+        input.with_prompt(&style(prompt).cyan().to_string());
+        if let Some(default) = default {
+            input.default(String::from(default));
+        }
+        Ok(input.interact()?)
+    }
+
+    fn progress(&self, len: u64) -> Box<dyn Progress> {
+        Box::new(
+            ProgressBar::new(len).with_style(
+                ProgressStyle::default_bar()
+                    .template("[{wide_bar}] {pos}/{len}")
+                    .progress_chars("=> "),
+            ),
+        )
+    }
+
+    fn message(&self, message: &str) {
+        if self.json_output {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+}
+
+impl Progress for ProgressBar {
+    fn inc(&self, delta: u64) {
+        ProgressBar::inc(self, delta);
+    }
+
+    fn finish(&self) {
+        self.finish_and_clear();
+    }
+}
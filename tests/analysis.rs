@@ -0,0 +1,332 @@
+use serde_json::Value;
+use spotify_tools::analysis::{self, Criteria, Duplicate, Keep, Reason, Strategy};
+use spotify_tools::api::models::{FullArtist, Track};
+use std::fs;
+use std::path::Path;
+
+/// Tracks and artists of the fixture library, in the order of the liked songs
+fn library() -> (Vec<Track>, Vec<FullArtist>) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/library.json");
+    let library: Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+    let tracks = library["saved_tracks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|id| {
+            let track = library["tracks"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|track| &track["id"] == id)
+                .unwrap();
+            serde_json::from_value(track.clone()).unwrap()
+        })
+        .collect();
+    let artists = serde_json::from_value(library["artists"].clone()).unwrap();
+    (tracks, artists)
+}
+
+fn ids<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Vec<&'a str> {
+    tracks.into_iter().map(|track| &track.id[..]).collect()
+}
+
+fn by(strategy: Strategy) -> Criteria {
+    Criteria {
+        strategy,
+        ..Criteria::default()
+    }
+}
+
+fn find(tracks: &[Track], criteria: Criteria) -> Vec<Duplicate<'_>> {
+    analysis::find_duplicates(tracks, &[], &criteria)
+}
+
+#[test]
+fn duplicates_keep_album_tracks() {
+    let (tracks, _) = library();
+    let duplicates = find(&tracks, by(Strategy::Name));
+
+    assert_eq!(
+        ids(duplicates.iter().map(|d| d.track)),
+        ["track2", "track6"]
+    );
+    assert_eq!(
+        duplicates.iter().map(|d| d.index).collect::<Vec<_>>(),
+        [1, 5]
+    );
+}
+
+#[test]
+fn single_is_replaced_by_later_album_track() {
+    let (mut tracks, _) = library();
+    // The single now comes first, the album track found later is still the one kept
+    tracks.swap(0, 1);
+    let duplicates = find(&tracks, by(Strategy::Name));
+
+    assert_eq!(duplicates[0].track.id, "track2");
+    assert_eq!(duplicates[0].index, 0);
+}
+
+#[test]
+fn isrc_finds_renamed_releases_of_recording() {
+    let (mut tracks, _) = library();
+    tracks[1].name = String::from("Song A - 2011 Remaster");
+    tracks[1].external_ids.isrc = Some(String::from("us-aaa-91-00001"));
+
+    let by_name = find(&tracks, by(Strategy::Name));
+    assert_eq!(ids(by_name.iter().map(|d| d.track)), ["track6"]);
+    let by_isrc = find(&tracks, by(Strategy::Isrc));
+    assert_eq!(ids(by_isrc.iter().map(|d| d.track)), ["track2", "track6"]);
+}
+
+#[test]
+fn isrc_tells_apart_recordings_with_same_name() {
+    let (mut tracks, _) = library();
+    // A live version, with the same title as the studio one
+    tracks[5].external_ids.isrc = Some(String::from("GBBBB9600001"));
+    // Without an ISRC nothing can be told about the track
+    tracks[0].external_ids.isrc = None;
+
+    let duplicates = find(&tracks, by(Strategy::Isrc));
+    assert!(duplicates.is_empty());
+}
+
+#[test]
+fn keep_policy_chooses_remaining_track() {
+    let (mut tracks, _) = library();
+    // track1 is on an album, track2 on a single released earlier but less popular
+    // track3 is on an album, track6 on a later and less popular compilation
+    let cases = [
+        (Keep::Album, ["track2", "track6"]),
+        (Keep::Single, ["track1", "track6"]),
+        (Keep::Compilation, ["track2", "track3"]),
+        (Keep::Earliest, ["track1", "track6"]),
+        (Keep::Latest, ["track2", "track3"]),
+        (Keep::Popular, ["track2", "track6"]),
+        (Keep::First, ["track2", "track6"]),
+        (Keep::Last, ["track1", "track3"]),
+    ];
+    for (keep, duplicates) in cases {
+        let found = find(
+            &tracks,
+            Criteria {
+                keep,
+                ..Criteria::default()
+            },
+        );
+        assert_eq!(ids(found.iter().map(|d| d.track)), duplicates, "{:?}", keep);
+    }
+
+    tracks[1].explicit = true;
+    let explicit = find(
+        &tracks,
+        Criteria {
+            keep: Keep::Explicit,
+            ..Criteria::default()
+        },
+    );
+    assert_eq!(ids(explicit.iter().map(|d| d.track)), ["track1", "track6"]);
+    let clean = find(
+        &tracks,
+        Criteria {
+            keep: Keep::Clean,
+            ..Criteria::default()
+        },
+    );
+    assert_eq!(ids(clean.iter().map(|d| d.track)), ["track2", "track6"]);
+}
+
+#[test]
+fn keep_policy_compares_release_dates_up_to_common_precision() {
+    let (mut tracks, _) = library();
+    // Same year as the single of track2, the lower album ID decides then
+    tracks[0].album.release_date = String::from("1991");
+    tracks[0].album.release_date_precision = String::from("year");
+    for (keep, duplicates) in [
+        (Keep::Earliest, ["track2", "track6"]),
+        (Keep::Latest, ["track2", "track3"]),
+    ] {
+        let found = find(
+            &tracks,
+            Criteria {
+                keep,
+                ..Criteria::default()
+            },
+        );
+        assert_eq!(ids(found.iter().map(|d| d.track)), duplicates, "{:?}", keep);
+    }
+}
+
+#[test]
+fn keep_first_added_puts_unknown_dates_last() {
+    let (tracks, _) = library();
+    let added_at = [
+        Some("2021-01-02T00:00:00Z"),
+        Some("2021-01-01T00:00:00Z"),
+        None,
+        None,
+        None,
+        Some("2021-01-03T00:00:00Z"),
+    ];
+    let criteria = Criteria {
+        keep: Keep::FirstAdded,
+        ..Criteria::default()
+    };
+    let duplicates = analysis::find_duplicates(&tracks, &added_at, &criteria);
+
+    assert_eq!(
+        ids(duplicates.iter().map(|d| d.track)),
+        ["track1", "track3"]
+    );
+}
+
+#[test]
+fn titles_are_normalized() {
+    let cases = [
+        ("Song A (feat. Someone)", "song a"),
+        ("Song A [with Someone Else]", "song a"),
+        ("Song A ft. Someone", "song a"),
+        ("Song A - Remastered 2009", "song a"),
+        ("Song A - 2011 Remaster", "song a"),
+        ("Song A (Radio Edit)", "song a"),
+        ("Song A – Deluxe Edition", "song a"),
+        ("Sóng Ä!", "song a"),
+        ("SONG, A.", "song a"),
+        ("Song A (Live)", "song a live"),
+        ("Song A - Acoustic Version", "song a acoustic version"),
+        ("Jay-Z's Song", "jay z s song"),
+        ("(Intro)", "intro"),
+    ];
+    for (title, normalized) in cases {
+        assert_eq!(analysis::normalize_title(title), normalized, "{}", title);
+    }
+}
+
+#[test]
+fn fuzzy_finds_variants_of_title() {
+    let (mut tracks, _) = library();
+    tracks[1].name = String::from("Song A (feat. Someone) - Remastered 2009");
+    tracks[5].name = String::from("SONG B!");
+    let duplicates = find(&tracks, by(Strategy::Fuzzy));
+
+    assert_eq!(
+        ids(duplicates.iter().map(|d| d.track)),
+        ["track2", "track6"]
+    );
+    assert_eq!(duplicates[0].key, "artist one - song a");
+    assert_eq!(duplicates[0].reason, Reason::SameNormalizedTitle);
+    assert_eq!(duplicates[0].reason.to_string(), "same normalized title");
+    // Only exactly equal names with the name strategy
+    let by_name = find(&tracks, by(Strategy::Name));
+    assert!(by_name.is_empty());
+}
+
+#[test]
+fn fuzzy_threshold_limits_similar_titles() {
+    let (tracks, _) = library();
+    // "song a" and "song d" differ by one letter out of six
+    let duplicates = find(
+        &tracks,
+        Criteria {
+            threshold: 0.8,
+            ..by(Strategy::Fuzzy)
+        },
+    );
+
+    assert_eq!(
+        ids(duplicates.iter().map(|d| d.track)),
+        ["track2", "track5", "track6"]
+    );
+    assert_eq!(duplicates[1].key, "artist one - song d");
+    match duplicates[1].reason {
+        Reason::SimilarTitle(similarity) => assert!((similarity - 5.0 / 6.0).abs() < 1e-9),
+        reason => panic!("unexpected reason {:?}", reason),
+    }
+    assert_eq!(duplicates[1].reason.to_string(), "similar title (83%)");
+
+    // Numbered pieces are different songs, however similar their titles
+    for (title, other) in [
+        ("Nocturne Op. 9 No. 1", "Nocturne Op. 9 No. 2"),
+        ("Song Part 1", "Song Part 2"),
+        ("Song, Pt. I", "Song, Pt. II"),
+        (
+            "Symphony No. 5 - II. Andante",
+            "Symphony No. 5 - III. Andante",
+        ),
+    ] {
+        let mut pair = vec![tracks[0].clone(), tracks[0].clone()];
+        pair[0].name = String::from(title);
+        pair[1].name = String::from(other);
+        let duplicates = find(
+            &pair,
+            Criteria {
+                threshold: 0.8,
+                ..by(Strategy::Fuzzy)
+            },
+        );
+        assert!(duplicates.is_empty(), "{} and {}", title, other);
+    }
+    let mut pair = vec![tracks[0].clone(), tracks[0].clone()];
+    pair[0].name = String::from("Nocturne Op. 9 No. 2");
+    pair[1].name = String::from("Nocturne, Op. 9 No. 2 - Remastered 2009");
+    assert_eq!(find(&pair, by(Strategy::Fuzzy)).len(), 1);
+}
+
+#[test]
+fn decades_are_sorted() {
+    let (tracks, _) = library();
+    let decades = analysis::group_by_decade(&tracks);
+
+    assert_eq!(
+        decades.iter().map(|d| &d.decade[..]).collect::<Vec<_>>(),
+        ["1980s", "1990s", "2000s"]
+    );
+    assert_eq!(
+        ids(decades[1].tracks.iter().copied()),
+        ["track1", "track2", "track5", "track6"]
+    );
+}
+
+#[test]
+fn genres_include_artists() {
+    let (tracks, artists) = library();
+    assert_eq!(
+        analysis::main_artists(&tracks),
+        ["artist1", "artist2", "artist3"]
+    );
+
+    let genres = analysis::group_by_genre(&tracks, &artists);
+    let names = genres.iter().map(|g| &g.genre[..]).collect::<Vec<_>>();
+    // Most tracks first, ties in alphabetical order
+    assert_eq!(
+        names,
+        [
+            "indie rock",
+            "Artist One",
+            "dream pop",
+            "Artist Two",
+            "synthpop",
+            "Artist Three"
+        ]
+    );
+    assert_eq!(genres[0].artists, ["Artist One", "Artist Three"]);
+}
+
+#[test]
+fn stats_count_every_artist() {
+    let (tracks, _) = library();
+    let stats = analysis::library_stats(&tracks);
+
+    assert_eq!(stats.songs, 6);
+    assert_eq!(stats.albums.len(), 5);
+    assert_eq!(stats.albums[0].id, "album1");
+    assert_eq!(stats.albums[0].count, 2);
+    assert_eq!(
+        stats
+            .artists
+            .iter()
+            .map(|a| (a.name, a.count))
+            .collect::<Vec<_>>(),
+        [("Artist One", 3), ("Artist Two", 2), ("Artist Three", 1)]
+    );
+}
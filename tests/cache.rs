@@ -0,0 +1,99 @@
+mod common;
+
+use common::FakeSpotify;
+
+const SAVED_TRACKS: &str = "/v1/me/tracks";
+const ARTISTS: &str = "/v1/artists";
+
+#[test]
+fn newly_liked_songs_are_downloaded_incrementally() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["tracks-info"]);
+    assert_eq!(spotify.downloads(SAVED_TRACKS), 3);
+
+    spotify.clear_requests();
+    spotify.like("track7");
+    let info = spotify.run_json(&["tracks-info"]);
+
+    assert_eq!(info["songs"], 7);
+    // The first page already reaches the cached songs
+    assert_eq!(spotify.downloads(SAVED_TRACKS), 1);
+}
+
+#[test]
+fn unliked_songs_make_liked_songs_download_again() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["duplicates", "--target", "liked", "--yes"]);
+
+    spotify.clear_requests();
+    let info = spotify.run_json(&["tracks-info"]);
+
+    assert_eq!(info["songs"], 4);
+    // The first page shows the total changed, then all two pages are downloaded
+    assert_eq!(spotify.downloads(SAVED_TRACKS), 3);
+}
+
+#[test]
+fn unchanged_playlists_are_not_downloaded_again() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["duplicates", "--target", "Road trip"]);
+
+    spotify.clear_requests();
+    let report = spotify.run_json(&["duplicates", "--target", "Road trip"]);
+
+    assert_eq!(report["duplicates"][0]["index"], 2);
+    assert_eq!(spotify.downloads("/v1/playlists/playlist1/tracks"), 0);
+}
+
+#[test]
+fn changed_playlists_are_downloaded_again() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["duplicates", "--target", "Road trip", "--yes"]);
+
+    spotify.clear_requests();
+    let report = spotify.run_json(&["duplicates", "--target", "Road trip"]);
+
+    assert_eq!(report["duplicates"].as_array().unwrap().len(), 0);
+    assert_eq!(spotify.downloads("/v1/playlists/playlist1/tracks"), 2);
+}
+
+#[test]
+fn cached_artists_are_not_downloaded_again() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["genres"]);
+    assert_eq!(spotify.downloads(ARTISTS), 1);
+
+    spotify.clear_requests();
+    spotify.run_json(&["genres"]);
+    assert_eq!(spotify.downloads(ARTISTS), 0);
+
+    spotify.run_json(&["genres", "--no-cache"]);
+    assert_eq!(spotify.downloads(ARTISTS), 1);
+}
+
+#[test]
+fn no_cache_downloads_everything() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["tracks-info"]);
+
+    spotify.clear_requests();
+    spotify.run_json(&["tracks-info", "--no-cache"]);
+
+    assert_eq!(spotify.downloads(SAVED_TRACKS), 3);
+}
+
+#[test]
+fn refresh_cache_downloads_whole_library() {
+    let spotify = FakeSpotify::start("library");
+    let info = spotify.run_json(&["refresh-cache"]);
+
+    assert_eq!(info["songs"], 6);
+    assert_eq!(info["artists"], 3);
+    assert_eq!(info["playlists"], 4);
+    assert_eq!(info["playlist_tracks"], 12);
+
+    spotify.clear_requests();
+    spotify.run_json(&["refresh-cache"]);
+    assert_eq!(spotify.downloads(SAVED_TRACKS), 3);
+    assert_eq!(spotify.downloads(ARTISTS), 1);
+}
@@ -0,0 +1,541 @@
+mod common;
+
+use common::FakeSpotify;
+use serde_json::json;
+
+fn uris(ids: &[&str]) -> Vec<String> {
+    ids.iter()
+        .map(|id| format!("spotify:track:{}", id))
+        .collect()
+}
+
+fn sorted(mut items: Vec<String>) -> Vec<String> {
+    items.sort();
+    items
+}
+
+#[test]
+fn tracks_info_summarizes_library() {
+    let spotify = FakeSpotify::start("library");
+    let info = spotify.run_json(&["tracks-info"]);
+
+    assert_eq!(info["songs"], 6);
+    assert_eq!(info["albums"], 5);
+    assert_eq!(info["artists"], 3);
+    assert_eq!(
+        info["top_artists"][0],
+        json!({ "id": "artist1", "name": "Artist One", "songs": 3 })
+    );
+}
+
+#[test]
+fn duplicates_are_removed_from_liked_songs() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "liked", "--yes"]);
+
+    let removed = report["duplicates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|duplicate| duplicate["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    // The single loses to the album, otherwise the lower album ID is kept
+    assert_eq!(removed, ["track2", "track6"]);
+    assert_eq!(report["target"], "liked");
+    assert_eq!(
+        spotify.saved_tracks(),
+        ["track1", "track3", "track4", "track5"]
+    );
+}
+
+#[test]
+fn duplicates_are_found_by_isrc() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "liked", "--by", "isrc"]);
+
+    assert_eq!(report["by"], "isrc");
+    let found = report["duplicates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|duplicate| duplicate["id"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(found, ["track2", "track6"]);
+}
+
+#[test]
+fn duplicates_report_normalized_key_and_reason() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "liked", "--by", "fuzzy"]);
+
+    assert_eq!(report["by"], "fuzzy");
+    assert_eq!(report["threshold"], 0.9);
+    assert_eq!(report["duplicates"][0]["id"], "track2");
+    assert_eq!(report["duplicates"][0]["key"], "artist one - song a");
+    assert_eq!(report["duplicates"][0]["reason"], "same normalized title");
+
+    let report = spotify.run_json(&["duplicates", "--target", "liked"]);
+    assert!(report["threshold"].is_null());
+    assert_eq!(report["duplicates"][0]["key"], "Artist One - Song A");
+    assert_eq!(report["duplicates"][0]["reason"], "same name");
+}
+
+#[test]
+fn duplicates_keep_policy_is_chosen_per_run() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "liked", "--keep", "single"]);
+
+    assert_eq!(report["keep"], "single");
+    assert_eq!(report["duplicates"][0]["id"], "track1");
+    let report = spotify.run_json(&["duplicates", "--target", "liked", "--keep", "first-added"]);
+    assert_eq!(report["keep"], "first-added");
+}
+
+#[test]
+fn review_with_json_output_exits_with_input_error() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&[
+        "--output",
+        "json",
+        "duplicates",
+        "--target",
+        "liked",
+        "--review",
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't be reviewed"));
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn threshold_out_of_range_exits_with_input_error() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&[
+        "duplicates",
+        "--target",
+        "liked",
+        "--by",
+        "fuzzy",
+        "--threshold",
+        "1.5",
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn duplicates_are_found_across_liked_songs_and_playlists() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "all"]);
+
+    let songs = report["songs"].as_array().unwrap();
+    assert_eq!(
+        songs.iter().map(|song| &song["key"]).collect::<Vec<_>>(),
+        [
+            "Artist One - Song A",
+            "Artist Two - Song B",
+            "Artist Three - Song C",
+            "Artist One - Song D"
+        ]
+    );
+    assert_eq!(songs[0]["overlaps"], json!(["liked_releases"]));
+    assert_eq!(
+        songs[1]["overlaps"],
+        json!([
+            "several_places",
+            "liked_releases",
+            "several_playlists",
+            "same_playlist"
+        ])
+    );
+    assert_eq!(
+        songs[2]["overlaps"],
+        json!(["several_places", "several_playlists"])
+    );
+    let copy = &songs[3]["copies"][1];
+    assert_eq!(copy["place"], "Road trip");
+    assert_eq!(copy["playlist_id"], "playlist1");
+    assert_eq!(copy["index"], 3);
+    assert_eq!(copy["reason"], "same name");
+    assert_eq!(copy["removed"], false);
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn copies_are_removed_from_chosen_places_only() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&[
+        "duplicates",
+        "--target",
+        "all",
+        "--remove-from",
+        "liked,Mixed",
+        "--yes",
+    ]);
+
+    assert!(output.status.success());
+    // Song A is only liked, the album track is left
+    assert_eq!(spotify.saved_tracks(), ["track1"]);
+    assert_eq!(
+        spotify.playlist("Mixed").unwrap().tracks,
+        ["local1", "removed", "episode1"]
+    );
+    assert_eq!(
+        spotify.playlist("Road trip").unwrap().tracks,
+        ["track3", "track4", "track3", "track5"]
+    );
+}
+
+#[test]
+fn remove_from_needs_all_targets() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&[
+        "duplicates",
+        "--target",
+        "liked",
+        "--remove-from",
+        "Road trip",
+        "--yes",
+    ]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--target all"));
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn duplicates_are_removed_from_playlist_by_position() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "Road trip", "--yes"]);
+
+    assert_eq!(report["playlist_id"], "playlist1");
+    assert_eq!(report["duplicates"][0]["index"], 2);
+
+    let modifications = spotify.modifications();
+    assert_eq!(modifications.len(), 1);
+    assert_eq!(modifications[0].method, "DELETE");
+    assert_eq!(modifications[0].path, "/v1/playlists/playlist1/tracks");
+    assert_eq!(
+        modifications[0].body,
+        json!({
+            "tracks": [{ "uri": "spotify:track:track3", "positions": [2] }],
+            "snapshot_id": "snapshot0"
+        })
+    );
+    // Only the repeated occurrence is gone, the first one stays in place
+    assert_eq!(
+        spotify.playlist("Road trip").unwrap().tracks,
+        ["track3", "track4", "track5"]
+    );
+}
+
+#[test]
+fn duplicates_are_only_reported_without_confirmation() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "liked"]);
+
+    assert_eq!(report["duplicates"].as_array().unwrap().len(), 2);
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn decades_creates_playlist() {
+    let spotify = FakeSpotify::start("library");
+    let decades = spotify.run_json(&[
+        "decades",
+        "--select",
+        "90s",
+        "--playlist-name",
+        "Nineties",
+        "--yes",
+    ]);
+
+    assert_eq!(
+        decades,
+        json!([
+            { "decade": "1980s", "tracks": uris(&["track3"]) },
+            { "decade": "1990s", "tracks": uris(&["track1", "track2", "track5", "track6"]) },
+            { "decade": "2000s", "tracks": uris(&["track4"]) },
+        ])
+    );
+
+    let playlist = spotify.playlist("Nineties").unwrap();
+    assert_eq!(playlist.owner, "tester");
+    assert_eq!(playlist.tracks, ["track1", "track2", "track5", "track6"]);
+}
+
+#[test]
+fn long_playlist_name_is_truncated_by_characters() {
+    let spotify = FakeSpotify::start("library");
+    let name = "€".repeat(150);
+    let output = spotify.run(&[
+        "decades",
+        "--select",
+        "90s",
+        "--playlist-name",
+        &name,
+        "--yes",
+    ]);
+
+    assert!(output.status.success());
+    assert!(spotify.playlist(&"€".repeat(100)).is_some());
+}
+
+#[test]
+fn genres_updates_existing_playlist() {
+    let spotify = FakeSpotify::start("library");
+    let genres = spotify.run_json(&[
+        "genres",
+        "--select",
+        "Indie Rock",
+        "--playlist-name",
+        "indie rock",
+        "--yes",
+    ]);
+
+    let indie = genres
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|genre| genre["genre"] == "indie rock")
+        .unwrap();
+    assert_eq!(indie["tracks"].as_array().unwrap().len(), 4);
+
+    // The playlist with the same name is updated instead of creating a second one
+    let playlist = spotify.playlist("indie rock").unwrap();
+    assert_eq!(playlist.id, "playlist3");
+    assert_eq!(
+        sorted(playlist.tracks),
+        ["track1", "track2", "track4", "track5"]
+    );
+    let deleted = spotify
+        .modifications()
+        .into_iter()
+        .find(|request| request.method == "DELETE")
+        .unwrap();
+    assert_eq!(
+        deleted.body["tracks"],
+        json!([{ "uri": "spotify:track:track6" }])
+    );
+}
+
+#[test]
+fn dry_run_leaves_library_unchanged() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&[
+        "decades",
+        "--select",
+        "1980s",
+        "--playlist-name",
+        "Eighties",
+        "--yes",
+        "--dry-run",
+    ]);
+
+    assert!(output.status.success());
+    assert!(spotify.modifications().is_empty());
+    assert!(spotify.playlist("Eighties").is_none());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("POST"));
+    assert!(stdout.contains("\"Eighties\""));
+}
+
+#[test]
+fn rate_limited_requests_are_retried() {
+    let spotify = FakeSpotify::start("library");
+    spotify.fail_next(1, 429, Some(0));
+    spotify.fail_next(2, 503, None);
+
+    let info = spotify.run_json(&["tracks-info"]);
+    assert_eq!(info["songs"], 6);
+}
+
+#[test]
+fn failed_modifications_are_not_repeated() {
+    let spotify = FakeSpotify::start("library");
+    // The playlist might have been created before the server failed
+    spotify.fail_next_of("POST", 1, 503, None);
+
+    let output = spotify.run(&["decades", "--select", "90s", "--yes"]);
+    assert!(!output.status.success());
+    assert_eq!(spotify.modifications().len(), 1);
+}
+
+#[test]
+fn rate_limited_modifications_are_retried() {
+    let spotify = FakeSpotify::start("library");
+    spotify.fail_next_of("POST", 1, 429, Some(0));
+
+    spotify.run_json(&[
+        "decades",
+        "--select",
+        "90s",
+        "--playlist-name",
+        "Nineties",
+        "--yes",
+    ]);
+    assert_eq!(
+        spotify.playlist("Nineties").unwrap().tracks,
+        ["track1", "track2", "track5", "track6"]
+    );
+}
+
+#[test]
+fn rejected_token_exits_with_auth_error() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run_with_token("expired", &["tracks-info"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn command_with_several_profiles_requires_one() {
+    let spotify = FakeSpotify::start("library");
+    spotify.save_profile("personal");
+    spotify.save_profile("team");
+    let output = spotify
+        .command(common::ACCESS_TOKEN)
+        .env_remove("SPOTIFY_TOOLS_ACCESS_TOKEN")
+        .args(["duplicates", "--target", "liked", "--yes"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--profile"));
+    assert!(spotify.requests().is_empty());
+}
+
+#[test]
+fn unknown_playlist_exits_with_input_error() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&["duplicates", "--target", "Nonexistent", "--yes"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn playlist_items_are_filtered_to_used_fields() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["duplicates", "--target", "Road trip"]);
+
+    let requests = spotify.requests();
+    let items = requests
+        .iter()
+        .filter(|request| request.path == "/v1/playlists/playlist1/tracks")
+        .collect::<Vec<_>>();
+    assert_eq!(items.len(), 2);
+    for request in items {
+        assert_eq!(request.query["additional_types"], "track,episode");
+        assert!(request.query["fields"].contains("external_ids.isrc"));
+        assert!(request.query["fields"].contains("track(type,is_local,"));
+        assert_eq!(request.query["market"], "from_token");
+    }
+}
+
+#[test]
+fn saved_tracks_are_requested_for_the_user_market() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["duplicates", "--target", "liked"]);
+
+    let requests = spotify.requests();
+    let pages = requests
+        .iter()
+        .filter(|request| request.path == "/v1/me/tracks")
+        .collect::<Vec<_>>();
+    assert!(!pages.is_empty());
+    for request in pages {
+        assert_eq!(request.query["market"], "from_token");
+    }
+}
+
+#[test]
+fn tracks_without_analysis_fields_are_still_decoded() {
+    let spotify = FakeSpotify::start("library");
+    spotify.update_track("track2", |track| {
+        let track = track.as_object_mut().unwrap();
+        for field in [
+            "popularity",
+            "duration_ms",
+            "explicit",
+            "disc_number",
+            "track_number",
+        ] {
+            track.remove(field);
+        }
+    });
+    let report = spotify.run_json(&["duplicates", "--target", "liked"]);
+
+    assert_eq!(report["duplicates"][0]["id"], "track2");
+}
+
+#[test]
+fn relinked_duplicates_are_removed_under_their_saved_id() {
+    let spotify = FakeSpotify::start("library");
+    spotify.relink("track2");
+    spotify.relink("track3");
+    let report = spotify.run_json(&["duplicates", "--target", "liked", "--yes"]);
+
+    assert_eq!(report["duplicates"][0]["id"], "track2");
+    assert_eq!(
+        spotify.saved_tracks(),
+        ["track1", "track3", "track4", "track5"]
+    );
+
+    spotify.run_json(&["duplicates", "--target", "Road trip", "--yes"]);
+    assert_eq!(
+        spotify.playlist("Road trip").unwrap().tracks,
+        ["track3", "track4", "track5"]
+    );
+}
+
+#[test]
+fn duplicates_skip_other_playlist_items() {
+    let spotify = FakeSpotify::start("library");
+    let report = spotify.run_json(&["duplicates", "--target", "Mixed", "--yes"]);
+
+    // Positions still count the local file, the unavailable item and the episode
+    assert_eq!(report["duplicates"].as_array().unwrap().len(), 1);
+    assert_eq!(report["duplicates"][0]["index"], 4);
+    assert_eq!(
+        spotify.playlist("Mixed").unwrap().tracks,
+        ["track3", "local1", "removed", "episode1"]
+    );
+}
+
+#[test]
+fn malformed_track_is_not_taken_for_local_file() {
+    let spotify = FakeSpotify::start("library");
+    spotify.add_to_playlist("Road trip", "malformed");
+    let output = spotify.run(&["duplicates", "--target", "Road trip", "--yes"]);
+
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing field `album`"));
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn updated_playlist_keeps_local_files() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&[
+        "decades",
+        "--select",
+        "80s",
+        "--playlist-name",
+        "Mixed",
+        "--yes",
+    ]);
+
+    let modifications = spotify.modifications();
+    assert_eq!(modifications.len(), 1);
+    assert_eq!(
+        modifications[0].body["tracks"],
+        json!([{ "uri": "spotify:episode:episode1" }])
+    );
+    assert_eq!(
+        spotify.playlist("Mixed").unwrap().tracks,
+        ["track3", "local1", "removed", "track3"]
+    );
+}
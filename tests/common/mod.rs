@@ -0,0 +1,556 @@
+//! Fake Spotify Web API serving a library from fixtures, and helpers running the commands against it
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use spotify_tools::cmds::CmdHandler;
+use spotify_tools::ui::ScriptedUi;
+use spotify_tools::{auth, Client, Config};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tempfile::TempDir;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub const ACCESS_TOKEN: &str = "test-token";
+
+/// Request received by the fake server
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub body: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    pub owner: String,
+    pub snapshot_id: String,
+    pub tracks: Vec<String>,
+}
+
+/// Canned response served instead of the next requests, e.g. to simulate rate limiting
+struct Failure {
+    /// Only requests with this method fail, any request if None
+    method: Option<String>,
+    status: u16,
+    retry_after: Option<u64>,
+}
+
+struct State {
+    base_url: String,
+    user: Value,
+    artists: HashMap<String, Value>,
+    /// Tracks, and the local files, episodes and unavailable (null) items of playlists
+    tracks: HashMap<String, Value>,
+    saved_tracks: Vec<String>,
+    /// When the songs liked by the tests were added, the fixture ones share a date
+    added_at: HashMap<String, String>,
+    playlists: Vec<Playlist>,
+    page_size: usize,
+    failures: VecDeque<Failure>,
+    requests: Vec<Recorded>,
+    snapshots: u32,
+}
+
+pub struct FakeSpotify {
+    server: Arc<Server>,
+    state: Arc<Mutex<State>>,
+    pub base_url: String,
+    /// Isolated config and cache directories, so that tests never see the user's real credentials
+    home: TempDir,
+}
+
+impl FakeSpotify {
+    /// Start a server with the library from `tests/fixtures/<fixture>.json`
+    pub fn start(fixture: &str) -> FakeSpotify {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(format!("{}.json", fixture));
+        let library: Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let port = server.server_addr().to_ip().unwrap().port();
+        let base_url = format!("http://127.0.0.1:{}/v1", port);
+
+        let by_id = |key: &str| {
+            library[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| (String::from(item["id"].as_str().unwrap()), item.clone()))
+                .collect::<HashMap<_, _>>()
+        };
+        let ids = |value: &Value| {
+            value
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|id| String::from(id.as_str().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let state = State {
+            base_url: base_url.clone(),
+            user: library["user"].clone(),
+            artists: by_id("artists"),
+            tracks: by_id("tracks")
+                .into_iter()
+                .chain(
+                    library["other_items"]
+                        .as_object()
+                        .into_iter()
+                        .flatten()
+                        .map(|(key, item)| (key.clone(), item.clone())),
+                )
+                .collect(),
+            saved_tracks: ids(&library["saved_tracks"]),
+            added_at: HashMap::new(),
+            playlists: library["playlists"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|playlist| Playlist {
+                    id: String::from(playlist["id"].as_str().unwrap()),
+                    name: String::from(playlist["name"].as_str().unwrap()),
+                    owner: String::from(playlist["owner"].as_str().unwrap()),
+                    snapshot_id: String::from("snapshot0"),
+                    tracks: ids(&playlist["tracks"]),
+                })
+                .collect(),
+            // Small pages, so that following `next` links is exercised
+            page_size: 2,
+            failures: VecDeque::new(),
+            requests: Vec::new(),
+            snapshots: 0,
+        };
+        let state = Arc::new(Mutex::new(state));
+
+        let handler_server = Arc::clone(&server);
+        let handler_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for request in handler_server.incoming_requests() {
+                handle(&handler_state, request);
+            }
+        });
+
+        FakeSpotify {
+            server,
+            state,
+            base_url,
+            home: TempDir::new().unwrap(),
+        }
+    }
+
+    /// Answer the next `count` requests with the given status instead of handling them
+    pub fn fail_next(&self, count: usize, status: u16, retry_after: Option<u64>) {
+        self.push_failures(None, count, status, retry_after);
+    }
+
+    /// Answer the next `count` requests with the given method with the given status
+    pub fn fail_next_of(&self, method: &str, count: usize, status: u16, retry_after: Option<u64>) {
+        self.push_failures(Some(method), count, status, retry_after);
+    }
+
+    fn push_failures(
+        &self,
+        method: Option<&str>,
+        count: usize,
+        status: u16,
+        retry_after: Option<u64>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        for _ in 0..count {
+            state.failures.push_back(Failure {
+                method: method.map(String::from),
+                status,
+                retry_after,
+            });
+        }
+    }
+
+    pub fn saved_tracks(&self) -> Vec<String> {
+        self.state.lock().unwrap().saved_tracks.clone()
+    }
+
+    /// Like a song, it comes first in the liked songs as the newest one
+    pub fn like(&self, id: &str) {
+        let mut state = self.state.lock().unwrap();
+        let added_at = format!("2021-01-01T00:00:{:02}Z", state.added_at.len());
+        state.added_at.insert(String::from(id), added_at);
+        state.saved_tracks.insert(0, String::from(id));
+    }
+
+    /// Add an item of the fixture to the end of a playlist
+    pub fn add_to_playlist(&self, name: &str, id: &str) {
+        let mut state = self.state.lock().unwrap();
+        let playlist = state
+            .playlists
+            .iter_mut()
+            .find(|playlist| playlist.name == name)
+            .unwrap();
+        playlist.tracks.push(String::from(id));
+    }
+
+    /// Change how a track of the fixture is served
+    pub fn update_track(&self, id: &str, update: impl FnOnce(&mut Value)) {
+        update(self.state.lock().unwrap().tracks.get_mut(id).unwrap());
+    }
+
+    /// Serve a track of the fixture as Spotify does when it's unavailable in the user's market,
+    /// relinked to another track that keeps the original one in `linked_from`
+    pub fn relink(&self, id: &str) {
+        self.update_track(id, |track| {
+            track["linked_from"] = json!({ "id": track["id"], "uri": track["uri"] });
+            track["id"] = json!(format!("{}relinked", id));
+            track["uri"] = json!(format!("spotify:track:{}relinked", id));
+            track["is_playable"] = json!(true);
+        });
+    }
+
+    pub fn playlist(&self, name: &str) -> Option<Playlist> {
+        self.state
+            .lock()
+            .unwrap()
+            .playlists
+            .iter()
+            .find(|playlist| playlist.name == name)
+            .cloned()
+    }
+
+    /// Requests changing the library, in the order they were received
+    pub fn modifications(&self) -> Vec<Recorded> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method != "GET")
+            .collect()
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Number of GET requests received for the given path
+    pub fn downloads(&self, path: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|request| request.method == "GET" && request.path == path)
+            .count()
+    }
+
+    pub fn clear_requests(&self) {
+        self.state.lock().unwrap().requests.clear();
+    }
+
+    /// Command handler connected to the server, answering prompts from the script of the UI
+    pub fn handler(&self, ui: &ScriptedUi) -> CmdHandler {
+        let config = Config {
+            api_url: self.base_url.clone(),
+            access_token: Some(String::from(ACCESS_TOKEN)),
+            // Running in process, the real cache directory of the user must be left alone
+            cache: false,
+            ..Config::default()
+        };
+        let token = auth::login(&config, "default").unwrap();
+        let client = Client::new(config, String::from("default"), token, Box::new(ui.clone()));
+        CmdHandler::new(client).unwrap()
+    }
+
+    /// Save an empty token for a profile, as if it had logged in before
+    pub fn save_profile(&self, name: &str) {
+        let dir = self.home.path().join("cache/spotify-tools/profiles");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(name).with_extension("token"), b"").unwrap();
+    }
+
+    /// Run the binary against the server, authorized with a fixed access token
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.run_with_token(ACCESS_TOKEN, args)
+    }
+
+    pub fn run_with_token(&self, token: &str, args: &[&str]) -> Output {
+        self.command(token).args(args).output().unwrap()
+    }
+
+    /// Command running the binary against the server, to which arguments and environment variables can be added
+    pub fn command(&self, token: &str) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_spotify-tools"));
+        for (key, _) in std::env::vars() {
+            if key.starts_with("SPOTIFY_TOOLS_") {
+                command.env_remove(key);
+            }
+        }
+        command
+            .env("SPOTIFY_TOOLS_API_URL", &self.base_url)
+            .env("SPOTIFY_TOOLS_ACCESS_TOKEN", token)
+            .env("HOME", self.home.path())
+            .env("XDG_CONFIG_HOME", self.home.path().join("config"))
+            .env("XDG_CACHE_HOME", self.home.path().join("cache"))
+            .stdin(Stdio::null());
+        command
+    }
+
+    /// Run the binary, check it succeeded and parse its standard output as JSON
+    pub fn run_json(&self, args: &[&str]) -> Value {
+        let output = self.run(&[args, &["--output", "json"]].concat());
+        assert!(
+            output.status.success(),
+            "command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+}
+
+impl Drop for FakeSpotify {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+fn json_response(status: u16, body: &Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap())
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    (
+        status,
+        json!({ "error": { "status": status, "message": message } }),
+    )
+}
+
+fn handle(state: &Mutex<State>, mut request: Request) {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body).unwrap();
+    let body = serde_json::from_str(&body).unwrap_or(Value::Null);
+
+    let mut state = state.lock().unwrap();
+    let url = url::Url::parse(&format!("http://localhost{}", request.url())).unwrap();
+    state.requests.push(Recorded {
+        method: request.method().to_string(),
+        path: String::from(url.path()),
+        query: url.query_pairs().into_owned().collect(),
+        body: body.clone(),
+    });
+
+    let failure = state.failures.iter().position(|failure| {
+        failure
+            .method
+            .as_ref()
+            .is_none_or(|method| method == &request.method().to_string())
+    });
+    if let Some(failure) = failure.and_then(|index| state.failures.remove(index)) {
+        let mut response = json_response(failure.status, &error(failure.status, "Injected").1);
+        if let Some(secs) = failure.retry_after {
+            response.add_header(Header::from_bytes("Retry-After", secs.to_string()).unwrap());
+        }
+        request.respond(response).unwrap();
+        return;
+    }
+
+    let authorized = request.headers().iter().any(|header| {
+        header.field.equiv("Authorization")
+            && header.value.as_str() == format!("Bearer {}", ACCESS_TOKEN)
+    });
+    let (status, response) = if authorized {
+        route(&mut state, request.method(), &url, &body)
+    } else {
+        error(401, "Invalid access token")
+    };
+    request.respond(json_response(status, &response)).unwrap();
+}
+
+fn route(state: &mut State, method: &Method, url: &url::Url, body: &Value) -> (u16, Value) {
+    let query = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+    let segments = url.path_segments().unwrap().collect::<Vec<_>>();
+    match (method, &segments[..]) {
+        (Method::Get, ["v1", "me"]) => (200, state.user.clone()),
+        (Method::Get, ["v1", "me", "tracks"]) => {
+            let items = state
+                .saved_tracks
+                .iter()
+                .map(|id| {
+                    let added_at = state
+                        .added_at
+                        .get(id)
+                        .map_or("2020-01-01T00:00:00Z", |date| date);
+                    json!({ "added_at": added_at, "track": state.tracks[id] })
+                })
+                .collect::<Vec<_>>();
+            (200, state.page(url, &query, items))
+        }
+        (Method::Delete, ["v1", "me", "tracks"]) => {
+            let ids = body.as_array().cloned().unwrap_or_default();
+            state
+                .saved_tracks
+                .retain(|id| !ids.iter().any(|removed| removed == id));
+            (200, Value::Null)
+        }
+        (Method::Get, ["v1", "me", "playlists"]) => {
+            let items = state
+                .playlists
+                .iter()
+                .map(|playlist| state.simplified_playlist(playlist))
+                .collect::<Vec<_>>();
+            (200, state.page(url, &query, items))
+        }
+        (Method::Post, ["v1", "users", user_id, "playlists"]) => {
+            state.snapshots += 1;
+            let playlist = Playlist {
+                id: format!("created{}", state.playlists.len() + 1),
+                name: String::from(body["name"].as_str().unwrap()),
+                owner: String::from(*user_id),
+                snapshot_id: format!("snapshot{}", state.snapshots),
+                tracks: Vec::new(),
+            };
+            let response = state.simplified_playlist(&playlist);
+            state.playlists.push(playlist);
+            (201, response)
+        }
+        (_, ["v1", "playlists", playlist_id, "tracks"]) => {
+            let index = match state
+                .playlists
+                .iter()
+                .position(|playlist| playlist.id == *playlist_id)
+            {
+                Some(index) => index,
+                None => return error(404, "Playlist not found"),
+            };
+            match method {
+                Method::Get => {
+                    let items = state.playlists[index]
+                        .tracks
+                        .iter()
+                        .map(|id| {
+                            json!({
+                                "added_at": "2020-02-01T00:00:00Z",
+                                "added_by": { "id": state.playlists[index].owner },
+                                "track": state.tracks[id],
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    (200, state.page(url, &query, items))
+                }
+                Method::Post => {
+                    let uris = body["uris"].as_array().unwrap();
+                    let ids = uris
+                        .iter()
+                        .map(|uri| state.id_of(uri.as_str().unwrap()))
+                        .collect::<Vec<_>>();
+                    state.playlists[index].tracks.extend(ids);
+                    (201, state.new_snapshot(index))
+                }
+                Method::Delete => {
+                    let mut tracks = state.playlists[index]
+                        .tracks
+                        .iter()
+                        .cloned()
+                        .map(Some)
+                        .collect::<Vec<_>>();
+                    for removed in body["tracks"].as_array().unwrap() {
+                        let id = &state.id_of(removed["uri"].as_str().unwrap())[..];
+                        match removed["positions"].as_array() {
+                            // Only the occurrences at the given positions are removed
+                            Some(positions) => {
+                                for position in positions {
+                                    let position = position.as_u64().unwrap() as usize;
+                                    if tracks[position].as_deref() == Some(id) {
+                                        tracks[position] = None;
+                                    }
+                                }
+                            }
+                            None => {
+                                for track in tracks.iter_mut() {
+                                    if track.as_deref() == Some(id) {
+                                        *track = None;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    state.playlists[index].tracks = tracks.into_iter().flatten().collect();
+                    (200, state.new_snapshot(index))
+                }
+                _ => error(405, "Method not allowed"),
+            }
+        }
+        (Method::Get, ["v1", "artists"]) => {
+            let artists = query["ids"]
+                .split(',')
+                .map(|id| state.artists.get(id).cloned().unwrap_or(Value::Null))
+                .collect::<Vec<_>>();
+            (200, json!({ "artists": artists }))
+        }
+        _ => error(404, "Service not found"),
+    }
+}
+
+impl State {
+    /// Page of `items` selected by the offset and limit query parameters
+    fn page(&self, url: &url::Url, query: &HashMap<String, String>, items: Vec<Value>) -> Value {
+        let offset = query
+            .get("offset")
+            .map_or(0, |offset| offset.parse().unwrap());
+        let limit = query
+            .get("limit")
+            .map_or(20, |limit| limit.parse::<usize>().unwrap())
+            .min(self.page_size);
+        let total = items.len();
+        let next = if offset + limit < total {
+            Value::String(format!(
+                "{}{}?offset={}&limit={}",
+                self.base_url,
+                url.path().trim_start_matches("/v1"),
+                offset + limit,
+                limit
+            ))
+        } else {
+            Value::Null
+        };
+        json!({
+            "href": url.as_str(),
+            "items": items.into_iter().skip(offset).take(limit).collect::<Vec<_>>(),
+            "limit": limit,
+            "offset": offset,
+            "next": next,
+            "previous": null,
+            "total": total,
+        })
+    }
+
+    /// Key of the item with the given URI, the one it's saved under when it's relinked
+    fn id_of(&self, uri: &str) -> String {
+        self.tracks
+            .iter()
+            .find(|(_, item)| item["linked_from"]["uri"] == uri || item["uri"] == uri)
+            .map(|(id, _)| id.clone())
+            .unwrap()
+    }
+
+    fn simplified_playlist(&self, playlist: &Playlist) -> Value {
+        json!({
+            "id": playlist.id,
+            "name": playlist.name,
+            "owner": { "id": playlist.owner, "display_name": playlist.owner },
+            "snapshot_id": playlist.snapshot_id,
+            "uri": format!("spotify:playlist:{}", playlist.id),
+            "tracks": {
+                "href": format!("{}/playlists/{}/tracks", self.base_url, playlist.id),
+                "total": playlist.tracks.len(),
+            },
+        })
+    }
+
+    fn new_snapshot(&mut self, index: usize) -> Value {
+        self.snapshots += 1;
+        let snapshot_id = format!("snapshot{}", self.snapshots);
+        self.playlists[index].snapshot_id = snapshot_id.clone();
+        json!({ "snapshot_id": snapshot_id })
+    }
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::FakeSpotify;
+use serde_json::json;
+
+#[test]
+fn mirror_stores_whole_library() {
+    let spotify = FakeSpotify::start("library");
+    let info = spotify.run_json(&["mirror"]);
+
+    assert_eq!(info["songs"], 6);
+    assert_eq!(info["playlists"], 4);
+    assert_eq!(info["artists"], 3);
+
+    let counts = spotify.run_json(&[
+        "query",
+        "SELECT (SELECT COUNT(*) FROM tracks) AS tracks, \
+         (SELECT COUNT(*) FROM albums) AS albums, \
+         (SELECT COUNT(*) FROM playlist_tracks) AS playlist_tracks, \
+         (SELECT COUNT(*) FROM artist_genres) AS genres",
+    ]);
+    assert_eq!(
+        counts,
+        json!([{ "tracks": 6, "albums": 5, "playlist_tracks": 9, "genres": 4 }])
+    );
+}
+
+#[test]
+fn query_joins_songs_artists_and_albums() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["mirror"]);
+
+    let artists = spotify.run_json(&[
+        "query",
+        "SELECT artists.name, COUNT(*) AS songs FROM saved_tracks \
+         JOIN tracks ON tracks.id = saved_tracks.track_id \
+         JOIN albums ON albums.id = tracks.album_id \
+         JOIN track_artists ON track_artists.track_id = tracks.id \
+         JOIN artists ON artists.id = track_artists.artist_id \
+         WHERE albums.release_year < 2000 \
+         GROUP BY artists.id HAVING songs >= 2 ORDER BY songs DESC",
+    ]);
+    assert_eq!(
+        artists,
+        json!([
+            { "name": "Artist One", "songs": 3 },
+            { "name": "Artist Two", "songs": 2 },
+        ])
+    );
+}
+
+#[test]
+fn mirror_stores_track_details() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["mirror"]);
+
+    let tracks = spotify.run_json(&[
+        "query",
+        "SELECT tracks.id, isrc, duration_ms, popularity, explicit, track_number, \
+         release_date_precision, total_tracks, added_at, added_by FROM playlist_tracks \
+         JOIN tracks ON tracks.id = playlist_tracks.track_id \
+         JOIN albums ON albums.id = tracks.album_id \
+         WHERE playlist_id = 'playlist3' ORDER BY position",
+    ]);
+    assert_eq!(
+        tracks[0],
+        json!({
+            "id": "track4",
+            "isrc": "USCCC0400001",
+            "duration_ms": 242000,
+            "popularity": 44,
+            "explicit": 0,
+            "track_number": 5,
+            "release_date_precision": "year",
+            "total_tracks": 12,
+            "added_at": "2020-02-01T00:00:00Z",
+            "added_by": "tester",
+        })
+    );
+    assert_eq!(tracks[1]["id"], "track6");
+}
+
+#[test]
+fn query_results_become_playlist() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["mirror"]);
+    spotify.run_json(&[
+        "query",
+        "SELECT tracks.uri FROM tracks \
+         JOIN track_artists ON track_artists.track_id = tracks.id \
+         JOIN artist_genres ON artist_genres.artist_id = track_artists.artist_id \
+         WHERE artist_genres.genre = 'indie rock' ORDER BY tracks.id",
+        "--playlist-name",
+        "Indie",
+        "--yes",
+    ]);
+
+    let playlist = spotify.playlist("Indie").unwrap();
+    assert_eq!(playlist.tracks, ["track1", "track2", "track4", "track5"]);
+}
+
+#[test]
+fn invalid_query_exits_with_input_error() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["mirror"]);
+
+    let output = spotify.run(&["query", "SELECT * FROM missing"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no such table: missing"));
+}
+
+#[test]
+fn query_requires_mirror() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&["query", "SELECT 1"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
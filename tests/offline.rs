@@ -0,0 +1,132 @@
+mod common;
+
+use common::FakeSpotify;
+
+#[test]
+fn analyses_run_against_cached_library() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["refresh-cache"]);
+
+    spotify.clear_requests();
+    let info = spotify.run_json(&["tracks-info", "--offline"]);
+    let genres = spotify.run_json(&["genres", "--offline"]);
+
+    assert_eq!(info["songs"], 6);
+    assert_eq!(genres[0]["genre"], "indie rock");
+    assert!(spotify.requests().is_empty());
+}
+
+#[test]
+fn missing_cache_exits_with_offline_error() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&["tracks-info", "--offline"]);
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(spotify.requests().is_empty());
+}
+
+#[test]
+fn offline_flag_accepts_usual_environment_values() {
+    let spotify = FakeSpotify::start("library");
+    // Nothing is cached until the first online run
+    for (value, code) in [("1", 7), ("yes", 7), ("0", 0), ("false", 0), ("", 0)] {
+        let output = spotify
+            .command(common::ACCESS_TOKEN)
+            .env("SPOTIFY_TOOLS_OFFLINE", value)
+            .arg("tracks-info")
+            .output()
+            .unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(code),
+            "SPOTIFY_TOOLS_OFFLINE={}",
+            value
+        );
+    }
+}
+
+#[test]
+fn offline_requires_cache() {
+    let spotify = FakeSpotify::start("library");
+    let output = spotify.run(&["tracks-info", "--offline", "--no-cache"]);
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn changes_are_queued_until_sync() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["refresh-cache"]);
+    spotify.run_json(&["duplicates", "--target", "liked", "--yes", "--offline"]);
+
+    assert!(spotify.modifications().is_empty());
+    assert_eq!(spotify.saved_tracks().len(), 6);
+
+    let output = spotify.run(&["sync"]);
+    assert!(output.status.success());
+    assert_eq!(spotify.modifications().len(), 1);
+    assert_eq!(
+        spotify.saved_tracks(),
+        ["track1", "track3", "track4", "track5"]
+    );
+
+    // Nothing is left to send
+    spotify.run(&["sync"]);
+    assert_eq!(spotify.modifications().len(), 1);
+}
+
+#[test]
+fn playlist_created_offline_gets_its_songs_on_sync() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["refresh-cache"]);
+    spotify.run_json(&[
+        "decades",
+        "--select",
+        "90s",
+        "--playlist-name",
+        "Nineties",
+        "--yes",
+        "--offline",
+    ]);
+    assert!(spotify.playlist("Nineties").is_none());
+
+    let output = spotify.run(&["sync"]);
+    assert!(output.status.success());
+    let playlist = spotify.playlist("Nineties").unwrap();
+    assert_eq!(playlist.tracks, ["track1", "track2", "track5", "track6"]);
+}
+
+#[test]
+fn repeated_changes_are_queued_once() {
+    let spotify = FakeSpotify::start("library");
+    spotify.run_json(&["refresh-cache"]);
+    for _ in 0..2 {
+        let output = spotify.run(&["duplicates", "--target", "Road trip", "--yes", "--offline"]);
+        assert!(output.status.success());
+    }
+    let args = [
+        "decades",
+        "--select",
+        "90s",
+        "--playlist-name",
+        "Nineties",
+        "--yes",
+        "--offline",
+    ];
+    spotify.run_json(&args);
+    // A second playlist would be created
+    let output = spotify.run(&args);
+    assert_eq!(output.status.code(), Some(7));
+
+    let output = spotify.run(&["sync"]);
+    assert!(output.status.success());
+    assert_eq!(
+        spotify.playlist("Road trip").unwrap().tracks,
+        ["track3", "track4", "track5"]
+    );
+    assert_eq!(
+        spotify.playlist("Nineties").unwrap().tracks,
+        ["track1", "track2", "track5", "track6"]
+    );
+    assert_eq!(spotify.modifications().len(), 3);
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use common::FakeSpotify;
+use spotify_tools::cmds::{DecadesOptions, DuplicatesOptions, GenresOptions};
+use spotify_tools::ui::{Answer, ScriptedUi};
+use spotify_tools::Error;
+
+#[test]
+fn playlist_is_created_from_selected_decades() {
+    let spotify = FakeSpotify::start("library");
+    let ui = ScriptedUi::new(vec![
+        Answer::MultiSelect(vec![1]),
+        Answer::Input(String::from("My 90s")),
+        Answer::Confirm(true),
+        Answer::Confirm(false),
+    ]);
+
+    spotify
+        .handler(&ui)
+        .decades(&DecadesOptions::default())
+        .unwrap();
+
+    assert_eq!(
+        ui.prompts(),
+        [
+            "Select decades to create your playlist from",
+            "Select the name of your new playlist",
+            "Do you want to proceed?",
+            "Do you want to view it now?",
+        ]
+    );
+    assert!(ui.remaining().is_empty());
+    assert_eq!(
+        spotify.playlist("My 90s").unwrap().tracks,
+        ["track1", "track2", "track5", "track6"]
+    );
+}
+
+#[test]
+fn existing_playlist_is_kept_when_update_is_declined() {
+    let spotify = FakeSpotify::start("library");
+    let ui = ScriptedUi::new(vec![
+        // "indie rock" has the most songs
+        Answer::MultiSelect(vec![0]),
+        // The default name is the selected genre, matching an existing playlist
+        Answer::Default,
+        Answer::Confirm(false),
+    ]);
+
+    spotify
+        .handler(&ui)
+        .genres(&GenresOptions::default())
+        .unwrap();
+
+    assert!(ui
+        .messages()
+        .iter()
+        .any(|message| message.starts_with("You are going to update an existing \"indie rock\"")));
+    assert!(ui
+        .messages()
+        .contains(&String::from("Didn't update the playlist.")));
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn empty_selection_creates_nothing() {
+    let spotify = FakeSpotify::start("library");
+    let ui = ScriptedUi::new(vec![Answer::Default]);
+
+    spotify
+        .handler(&ui)
+        .decades(&DecadesOptions::default())
+        .unwrap();
+
+    assert!(ui
+        .messages()
+        .contains(&String::from("No decades selected.")));
+    assert!(spotify.modifications().is_empty());
+}
+
+#[test]
+fn duplicates_are_removed_from_selected_playlist() {
+    let spotify = FakeSpotify::start("library");
+    let ui = ScriptedUi::new(vec![
+        Answer::Select(1),
+        Answer::Default,
+        Answer::Default,
+        Answer::Confirm(false),
+        Answer::Confirm(true),
+    ]);
+
+    spotify
+        .handler(&ui)
+        .duplicates(&DuplicatesOptions::default())
+        .unwrap();
+
+    assert_eq!(
+        ui.prompts()[..3],
+        [
+            "Remove duplicates from",
+            "Find duplicates by",
+            "Keep from each group of duplicates"
+        ]
+    );
+    assert!(ui.messages().contains(&String::from(
+        "Artist Two - Song B (Second Album) [same name: Artist Two - Song B]"
+    )));
+    assert_eq!(
+        spotify.playlist("Road trip").unwrap().tracks,
+        ["track3", "track4", "track5"]
+    );
+}
+
+#[test]
+fn duplicates_are_reviewed_per_group() {
+    let spotify = FakeSpotify::start("library");
+    // Keep the single instead of the album track, then skip the second group
+    let ui = ScriptedUi::new(vec![Answer::Select(1), Answer::Select(2)]);
+
+    spotify
+        .handler(&ui)
+        .duplicates(&DuplicatesOptions {
+            target: Some(String::from("liked")),
+            review: true,
+            ..DuplicatesOptions::default()
+        })
+        .unwrap();
+
+    assert_eq!(ui.prompts(), ["Track to keep", "Track to keep"]);
+    assert!(ui
+        .messages()
+        .contains(&String::from("Group 1 of 2: Artist One - Song A")));
+    assert_eq!(
+        spotify.saved_tracks(),
+        ["track2", "track3", "track4", "track5", "track6"]
+    );
+}
+
+#[test]
+fn copies_are_removed_from_selected_places() {
+    let spotify = FakeSpotify::start("library");
+    let ui = ScriptedUi::new(vec![
+        Answer::Select(4),
+        Answer::Default,
+        Answer::Default,
+        Answer::MultiSelect(vec![2]),
+        Answer::Confirm(true),
+    ]);
+
+    spotify
+        .handler(&ui)
+        .duplicates(&DuplicatesOptions::default())
+        .unwrap();
+
+    assert!(ui.messages().contains(&String::from(
+        "Artist Three - Song C (in several places, in several playlists)"
+    )));
+    assert_eq!(
+        ui.prompts()[3..],
+        ["Remove copies from", "Do you want to delete 2 copies?"]
+    );
+    assert!(spotify.playlist("indie rock").unwrap().tracks.is_empty());
+    assert_eq!(spotify.saved_tracks().len(), 6);
+}
+
+#[test]
+fn duplicates_are_kept_by_default() {
+    let spotify = FakeSpotify::start("library");
+    let ui = ScriptedUi::new(vec![
+        Answer::Default,
+        Answer::Default,
+        Answer::Default,
+        Answer::Default,
+        Answer::Default,
+    ]);
+
+    spotify
+        .handler(&ui)
+        .duplicates(&DuplicatesOptions::default())
+        .unwrap();
+
+    assert!(ui
+        .messages()
+        .contains(&String::from("No duplicates removed.")));
+    assert!(spotify.modifications().is_empty());
+    assert_eq!(spotify.saved_tracks().len(), 6);
+}
+
+#[test]
+fn unanswered_prompt_is_an_input_error() {
+    let spotify = FakeSpotify::start("library");
+    let ui = ScriptedUi::new(vec![]);
+
+    let result = spotify
+        .handler(&ui)
+        .duplicates(&DuplicatesOptions::default());
+
+    assert!(matches!(result, Err(Error::Input(_))));
+    assert!(spotify.modifications().is_empty());
+}